@@ -0,0 +1,106 @@
+//! Benchmarks for the hashmap, run with `cargo bench`. Each benchmark reports
+//! the mean time of a single run.
+
+use std::{hint::black_box, time::Instant};
+
+use bindgen_hashmap::HashMap;
+
+/// Number of keys used by the bulk benchmarks
+const KEYS: usize = 100_000;
+
+/// Run the function once to warm up, then the given number of times, printing
+/// the mean duration of a run.
+fn bench<T>(name: &str, runs: u32, mut f: impl FnMut() -> T) {
+    black_box(f());
+    let start = Instant::now();
+    for _ in 0..runs {
+        black_box(f());
+    }
+    let mean = start.elapsed() / runs;
+    println!("{name:<40} {mean:>12.2?}");
+}
+
+fn main() {
+    let keys: Vec<String> = (0..KEYS).map(|i| i.to_string()).collect();
+
+    bench("bulk insert", 20, || {
+        let mut map = HashMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            map.insert(key, Box::new(i));
+        }
+        map
+    });
+    bench("bulk insert after reserve", 20, || {
+        let mut map = HashMap::new();
+        map.reserve(keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            map.insert(key, Box::new(i));
+        }
+        map
+    });
+    bench("bulk insert after reserve, unchecked", 20, || {
+        let mut map = HashMap::new();
+        map.reserve(keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            // SAFETY: Keys formatted from integers never contain nul bytes
+            unsafe { map.insert_unchecked(key, Box::new(i)) };
+        }
+        map
+    });
+
+    for (name, mut map) in [
+        ("lookup short keys", HashMap::new()),
+        (
+            "lookup short keys stored inline",
+            HashMap::builder().inline_keys().build(),
+        ),
+        (
+            "lookup short keys with open addressing",
+            HashMap::with_open_addressing(),
+        ),
+    ] {
+        for (i, key) in keys.iter().enumerate() {
+            map.insert(key, Box::new(i));
+        }
+        bench(name, 20, || {
+            let mut sum = 0;
+            for key in &keys {
+                sum += **map.get(key).unwrap();
+            }
+            sum
+        });
+    }
+
+    let source: HashMap<usize> = keys.iter().cloned().zip(0..).collect();
+    let mut target = source.clone();
+    bench("clone_from into the same hashmap", 20, || {
+        target.clone_from(&source);
+        target.len()
+    });
+    bench("assign a fresh clone", 20, || {
+        target = source.clone();
+        target.len()
+    });
+
+    let mut map = HashMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        map.insert(key, Box::new(i));
+    }
+    bench("interleaved remove and insert", 20, || {
+        for key in &keys {
+            let value = map.remove(key);
+            map.insert_boxed(key, value.unwrap());
+        }
+        map.len()
+    });
+
+    let mut map = HashMap::new();
+    map.insert("key", Box::new(0));
+    bench("is_empty x1,000,000", 20, || {
+        let mut empty = 0;
+        for _ in 0..1_000_000 {
+            empty += black_box(&map).is_empty() as usize;
+        }
+        empty
+    });
+}
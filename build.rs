@@ -25,19 +25,37 @@ fn main() {
         .flag("-Wstrict-overflow")
         .flag("-fno-strict-aliasing")
         .flag("-std=c2x")
-        .flag("-march=native")
-        .compile("hashmap");
+        .flag("-march=native");
+    // Without std, the C hashmap allocates through the Rust allocator instead
+    // of assuming a hosted libc one.
+    if env::var_os("CARGO_FEATURE_STD").is_none() {
+        builder.define("HASHMAP_RUST_ALLOC", None);
+    }
+    // Counting probes changes the layout of the HashMap struct, so bindgen has
+    // to see the same definition.
+    let probe_stats = env::var_os("CARGO_FEATURE_PROBE_STATS").is_some();
+    if probe_stats {
+        builder.define("HASHMAP_PROBE_STATS", None);
+    }
+    builder.compile("hashmap");
 
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
     // the resulting bindings.
-    let bindings = bindgen::Builder::default()
+    let mut bindings = bindgen::Builder::default()
         // The input header we would like to generate
         // bindings for.
         .header("wrapper.h")
+        // Only refer to core so that the bindings also work without std.
+        .use_core()
+        .ctypes_prefix("::core::ffi")
         // Tell cargo to invalidate the built crate whenever any of the
         // included header files changed.
-        .parse_callbacks(Box::new(bindgen::CargoCallbacks))
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks));
+    if probe_stats {
+        bindings = bindings.clang_arg("-DHASHMAP_PROBE_STATS");
+    }
+    let bindings = bindings
         // Finish the builder and generate the bindings.
         .generate()
         // Unwrap the Result and panic on failure.
@@ -0,0 +1,201 @@
+//! Builder for configuring a [`HashMap`] before it is created.
+
+use core::{ffi::c_char, marker::PhantomData};
+
+use crate::{hashmap, HashMap};
+
+/// Builder for a [`HashMap`] with custom options, created by
+/// [`HashMap::builder`]. All options are applied while the hashmap is still
+/// empty, so none of them have to deal with keys that were already inserted.
+/// Options that are not set keep the defaults of [`HashMap::new`].
+pub struct HashMapBuilder<V> {
+    /// Number of buckets to allocate up front, if any
+    buckets: Option<usize>,
+    /// Maximum load factor before growing
+    max_load_factor: Option<f64>,
+    /// Factor the number of buckets is multiplied by when growing
+    growth_factor: Option<f64>,
+    /// Function used to hash keys in place of SipHash
+    hasher: Option<extern "C" fn(*const c_char, usize) -> u64>,
+    /// Whether entries are allocated from an arena
+    arena: bool,
+    /// Whether short keys are stored inline in their entries
+    inline_keys: bool,
+    /// Phantom data to show which hashmap the builder creates
+    value: PhantomData<V>,
+}
+
+impl<V> HashMapBuilder<V> {
+    /// Create a new builder with every option left at its default.
+    pub(crate) fn new() -> Self {
+        Self {
+            buckets: None,
+            max_load_factor: None,
+            growth_factor: None,
+            hasher: None,
+            arena: false,
+            inline_keys: false,
+            value: PhantomData,
+        }
+    }
+
+    /// Allocate the given number of buckets up front, like
+    /// [`HashMap::with_buckets`].
+    pub fn buckets(mut self, buckets: usize) -> Self {
+        self.buckets = Some(buckets);
+        self
+    }
+
+    /// Set the load factor the hashmap may reach before it grows, like
+    /// [`HashMap::set_max_load_factor`]. [`HashMapBuilder::build`] panics if
+    /// the factor is not between 0.1 inclusive and 1 exclusive.
+    pub fn max_load_factor(mut self, factor: f64) -> Self {
+        self.max_load_factor = Some(factor);
+        self
+    }
+
+    /// Set the factor the number of buckets is multiplied by whenever the
+    /// hashmap grows, like [`HashMap::set_growth_factor`].
+    /// [`HashMapBuilder::build`] panics if the factor is not finite and greater
+    /// than 1.
+    pub fn growth_factor(mut self, factor: f64) -> Self {
+        self.growth_factor = Some(factor);
+        self
+    }
+
+    /// Hash keys with the given function instead of SipHash, like
+    /// [`HashMap::with_hasher`].
+    pub fn hasher(mut self, hasher: extern "C" fn(*const c_char, usize) -> u64) -> Self {
+        self.hasher = Some(hasher);
+        self
+    }
+
+    /// Allocate entries and keys from an arena that is only freed as a whole,
+    /// like [`HashMap::with_arena`].
+    pub fn arena(mut self) -> Self {
+        self.arena = true;
+        self
+    }
+
+    /// Store keys of at most 15 bytes inline in the same allocation as their
+    /// entry, so that comparing them during lookups does not have to follow a
+    /// pointer to a separate allocation. Longer keys are stored as usual.
+    pub fn inline_keys(mut self) -> Self {
+        self.inline_keys = true;
+        self
+    }
+
+    /// Create the hashmap with every option that was set.
+    ///
+    /// # Panics
+    /// Panics if the maximum load factor or growth factor is out of range, or
+    /// if an arena was requested and cannot be allocated.
+    pub fn build(self) -> HashMap<V> {
+        let mut map = match self.buckets {
+            Some(buckets) => HashMap::with_buckets(buckets),
+            None => HashMap::new(),
+        };
+        if let Some(hasher) = self.hasher {
+            unsafe { hashmap::hashmap_set_hasher(map.ptr, Some(hasher)) };
+        }
+        if let Some(factor) = self.max_load_factor {
+            map.set_max_load_factor(factor);
+        }
+        if let Some(factor) = self.growth_factor {
+            map.set_growth_factor(factor);
+        }
+        if self.inline_keys {
+            unsafe { hashmap::hashmap_set_inline_keys(map.ptr, true) };
+        }
+        if self.arena {
+            assert!(
+                unsafe { hashmap::hashmap_use_arena(map.ptr) },
+                "failed to allocate hashmap arena"
+            );
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tests::constant_hasher, HashMap};
+
+    #[test]
+    fn test_builder_defaults() {
+        let map = HashMap::<i32>::builder().build();
+        assert_eq!(map.bucket_count(), 0);
+        assert_eq!(map.max_load_factor(), 0.75);
+        assert_eq!(map.growth_factor(), 2.0);
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut map = HashMap::builder()
+            .buckets(16)
+            .max_load_factor(0.5)
+            .growth_factor(1.5)
+            .hasher(constant_hasher)
+            .build();
+        assert_eq!(map.bucket_count(), 16);
+        assert_eq!(map.capacity(), 8);
+        for i in 0..9 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.bucket_count(), 24);
+        assert_eq!(map.bucket_stats().max_chain_len, 9);
+        for i in 0..9 {
+            assert_eq!(map.get(&i.to_string()), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_builder_arena() {
+        let mut map = HashMap::builder().buckets(16).arena().build();
+        for i in 0..100 {
+            map.insert(&i.to_string(), i);
+        }
+        let copy = map.clone();
+        assert_eq!(copy.get("42"), Some(&42));
+        assert!(unsafe { !(*copy.ptr).arena.is_null() });
+    }
+
+    #[test]
+    fn test_builder_inline_keys() {
+        let mut map = HashMap::builder().inline_keys().build();
+        let keys = [
+            "",
+            "short",
+            "exactly15 bytes",
+            "sixteen bytes!!!",
+            "a much longer key",
+        ];
+        for (i, key) in keys.iter().enumerate() {
+            map.insert(*key, i);
+        }
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(map.get(*key), Some(&i));
+        }
+        let copy = map.clone();
+        assert!(unsafe { (*copy.ptr).inline_keys });
+        assert_eq!(copy, map);
+
+        assert_eq!(
+            map.remove_entry("short"),
+            Some(("short".to_string(), Box::new(1)))
+        );
+        assert_eq!(
+            map.remove_entry("a much longer key").unwrap().0,
+            "a much longer key"
+        );
+        assert_eq!(map.remove("exactly15 bytes"), Some(Box::new(2)));
+        map.retain(|key, _| key.is_empty());
+        assert_eq!(map.keys().collect::<Vec<_>>(), [""]);
+    }
+
+    #[test]
+    #[should_panic(expected = "max load factor")]
+    fn test_builder_invalid() {
+        HashMap::<i32>::builder().max_load_factor(2.0).build();
+    }
+}
@@ -0,0 +1,142 @@
+//! Cursor over the entries of a [`HashMap`] whose position can be saved and
+//! resumed from later.
+
+use core::{ffi::c_char, iter::FusedIterator, ptr};
+
+use crate::{hashmap, iter::key_str, HashMap};
+
+/// Position of a [`Cursor`] in the walk over the buckets of a hashmap: the next
+/// entry is the one `offset` entries into the chain of bucket `bucket`, or the
+/// first one after it. The fields are public so the position can be stored
+/// anywhere and turned back into a cursor with [`HashMap::cursor_at`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CursorPosition {
+    /// Index of the bucket the next entry is in
+    pub bucket: usize,
+    /// Number of entries of the bucket that have already been yielded
+    pub offset: usize,
+}
+
+/// Iterator over the key-value pairs of a [`HashMap`], created by
+/// [`HashMap::cursor`], that can be paused and resumed through its
+/// [`CursorPosition`]. Entries are yielded bucket by bucket, even if the
+/// hashmap is ordered, and entries whose keys are not valid UTF-8 are skipped
+/// as in [`Iter`](crate::Iter).
+///
+/// A position only stays meaningful as long as the hashmap is not modified.
+/// Resuming from one after the hashmap changed is safe, but entries may be
+/// skipped or yielded twice, since they may have moved to other buckets.
+///
+/// ```
+/// use bindgen_hashmap::HashMap;
+///
+/// let map: HashMap<i32> = (0..10).map(|i| (i.to_string(), i)).collect();
+/// let mut cursor = map.cursor();
+/// let mut sum = cursor.by_ref().take(4).map(|(_, v)| v).sum::<i32>();
+/// let position = cursor.position();
+/// drop(cursor);
+///
+/// sum += map.cursor_at(position).map(|(_, v)| v).sum::<i32>();
+/// assert_eq!(sum, 45);
+/// ```
+pub struct Cursor<'a, V> {
+    /// Hashmap being walked
+    map: &'a HashMap<V>,
+    /// Position of the next entry
+    position: hashmap::HashMapPosition,
+}
+
+impl<'a, V> Cursor<'a, V> {
+    /// Create a new cursor over the given hashmap starting at the position.
+    pub(crate) fn new(map: &'a HashMap<V>, position: CursorPosition) -> Self {
+        Self {
+            map,
+            position: hashmap::HashMapPosition {
+                bucket: position.bucket,
+                offset: position.offset,
+            },
+        }
+    }
+
+    /// Returns the position of the cursor, from which
+    /// [`HashMap::cursor_at`] resumes with the entry that would have been
+    /// yielded next.
+    pub fn position(&self) -> CursorPosition {
+        CursorPosition {
+            bucket: self.position.bucket,
+            offset: self.position.offset,
+        }
+    }
+
+    /// Returns the key-value pair that [`Iterator::next`] would yield next
+    /// without advancing the cursor.
+    pub fn peek(&self) -> Option<(&'a str, &'a V)> {
+        Self {
+            map: self.map,
+            position: self.position,
+        }
+        .next()
+    }
+}
+
+impl<'a, V> Iterator for Cursor<'a, V> {
+    type Item = (&'a str, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut key: *const c_char = ptr::null();
+        let mut key_len = 0;
+        let mut value = ptr::null_mut();
+        unsafe {
+            while hashmap::hashmap_position_next(
+                self.map.ptr,
+                &mut self.position,
+                &mut key,
+                &mut key_len,
+                &mut value,
+            ) {
+                if let Some(key) = key_str(ptr::slice_from_raw_parts(key as *const u8, key_len)) {
+                    return Some((key, &*(value as *const V)));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<V> FusedIterator for Cursor<'_, V> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CursorPosition, HashMap};
+
+    #[test]
+    fn test_cursor() {
+        let mut map = HashMap::with_buckets(4);
+        for i in 0..20 {
+            map.insert(&i.to_string(), i);
+        }
+        map.insert_bytes(&[0xff], -1);
+
+        let mut cursor = map.cursor();
+        let mut seen = Vec::new();
+        while let Some((key, value)) = cursor.peek() {
+            assert_eq!(cursor.next(), Some((key, value)));
+            seen.push(*value);
+            // Resume from a saved position every few entries
+            if seen.len() % 3 == 0 {
+                let position = cursor.position();
+                cursor = map.cursor_at(position);
+            }
+        }
+        assert_eq!(cursor.next(), None);
+        seen.sort();
+        assert_eq!(seen, (0..20).collect::<Vec<_>>());
+
+        let end = CursorPosition {
+            bucket: map.bucket_count(),
+            offset: 0,
+        };
+        assert_eq!(map.cursor_at(end).next(), None);
+        assert_eq!(HashMap::<i32>::new().cursor().peek(), None);
+    }
+}
@@ -0,0 +1,644 @@
+//! Entry API for in-place manipulation of a single key in a [`HashMap`], along
+//! with the raw entry API for callers that compute hashes themselves.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{ffi::c_void, mem, ptr, str};
+
+use crate::{hashmap, HashMap, HashMapKey};
+
+/// A view into a single key of a [`HashMap`], which may or may not have a value
+/// yet, created by [`HashMap::entry`]. The key is looked up exactly once when
+/// the entry is created and, if it is missing, inserted later without hashing
+/// it again. Matching on the variants gives access to the methods of
+/// [`OccupiedEntry`] and [`VacantEntry`].
+///
+/// ```
+/// use bindgen_hashmap::{Entry, HashMap};
+///
+/// let mut map = HashMap::new();
+/// map.insert("count", 1);
+/// if let Entry::Occupied(entry) = map.entry("count") {
+///     if *entry.get() == 1 {
+///         entry.remove();
+///     }
+/// }
+/// assert!(map.is_empty());
+/// ```
+pub enum Entry<'a, V> {
+    /// The key has a value.
+    Occupied(OccupiedEntry<'a, V>),
+    /// The key has no value.
+    Vacant(VacantEntry<'a, V>),
+}
+
+impl<'a, V> Entry<'a, V> {
+    /// Look up the given key in the hashmap, remembering where its value lives.
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte.
+    pub(crate) fn new(map: &'a mut HashMap<V>, key: &(impl HashMapKey + ?Sized)) -> Self {
+        let key = key_bytes(key);
+        let (hash, slot) = lookup(map, &key);
+        Self::from_slot(map, key, hash, slot)
+    }
+
+    /// Look up the given key in the hashmap using a hash computed beforehand,
+    /// remembering where its value lives.
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte.
+    pub(crate) fn with_hash(
+        map: &'a mut HashMap<V>,
+        key: &(impl HashMapKey + ?Sized),
+        hash: u64,
+    ) -> Self {
+        let key = key_bytes(key);
+        let slot = unsafe {
+            hashmap::hashmap_get_slot_hashed(
+                map.ptr,
+                key.as_ptr() as *const c_void,
+                key.len(),
+                hash,
+            )
+        };
+        Self::from_slot(map, key, hash, slot)
+    }
+
+    /// Create the entry for the result of looking up the key, which is vacant
+    /// if the slot is null.
+    fn from_slot(map: &'a mut HashMap<V>, key: Vec<u8>, hash: u64, slot: *mut *mut c_void) -> Self {
+        if slot.is_null() {
+            Self::Vacant(VacantEntry { map, key, hash })
+        } else {
+            Self::Occupied(OccupiedEntry {
+                map,
+                key,
+                hash,
+                slot,
+            })
+        }
+    }
+
+    /// Returns the key of this entry.
+    ///
+    /// # Panics
+    /// Panics if the key is not valid UTF-8, which only keys given as bytes or
+    /// integers can be. Use [`Entry::key_bytes`] for those.
+    pub fn key(&self) -> &str {
+        match self {
+            Self::Occupied(entry) => entry.key(),
+            Self::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Returns the bytes of the key of this entry, whatever type it was given
+    /// as.
+    pub fn key_bytes(&self) -> &[u8] {
+        match self {
+            Self::Occupied(entry) => entry.key_bytes(),
+            Self::Vacant(entry) => entry.key_bytes(),
+        }
+    }
+
+    /// Ensure a value is present by inserting the default if the key is vacant,
+    /// then return a mutable reference to the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Ensure a value is present by inserting the result of the default
+    /// function if the key is vacant, then return a mutable reference to the
+    /// value. The function is only called if the key is vacant.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Ensure a value is present like [`Entry::or_insert_with`], but with a
+    /// default function that can fail. Nothing is inserted if it returns an
+    /// error, which is passed on to the caller.
+    pub(crate) fn or_try_insert_with<F, E>(self, default: F) -> Result<&'a mut V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        Ok(match self {
+            Self::Occupied(entry) => entry.into_mut(),
+            Self::Vacant(entry) => entry.insert(default()?),
+        })
+    }
+
+    /// Call the function on the value if the key is occupied, then return the
+    /// entry for further chaining. The value is reached through the slot found
+    /// when the entry was created, so chaining `or_insert` afterwards still
+    /// looks the key up only once.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Self::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, V: Default> Entry<'a, V> {
+    /// Ensure a value is present by inserting the default value if the key is
+    /// vacant, then return a mutable reference to the value. The default value
+    /// is only created if the key is vacant.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+/// View into a key of a [`HashMap`] that has a value, part of [`Entry`].
+pub struct OccupiedEntry<'a, V> {
+    /// Hashmap the entry belongs to
+    map: &'a mut HashMap<V>,
+    /// Bytes of the key the entry was looked up with
+    key: Vec<u8>,
+    /// Hash of the key, which locates the bucket of the entry
+    hash: u64,
+    /// Pointer to the value slot of the key in the C hashmap
+    slot: *mut *mut c_void,
+}
+
+impl<'a, V> OccupiedEntry<'a, V> {
+    /// Returns the key the entry was looked up with. For a case insensitive
+    /// hashmap, it may differ in case from the stored key.
+    ///
+    /// # Panics
+    /// Panics if the key is not valid UTF-8, like [`Entry::key`].
+    pub fn key(&self) -> &str {
+        key_str(&self.key)
+    }
+
+    /// Returns the bytes of the key the entry was looked up with.
+    pub fn key_bytes(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Returns a reference to the value of the entry.
+    pub fn get(&self) -> &V {
+        unsafe { &*(*self.slot as *const V) }
+    }
+
+    /// Returns a mutable reference to the value of the entry.
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe { &mut *(*self.slot as *mut V) }
+    }
+
+    /// Turn the entry into a mutable reference to its value that lives as long
+    /// as the borrow of the hashmap.
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe { &mut *(*self.slot as *mut V) }
+    }
+
+    /// Replace the value of the entry, returning the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Remove the entry from the hashmap and return its value. The entry is
+    /// unlinked through the slot found during the lookup, so the key is not
+    /// looked up again.
+    pub fn remove(self) -> Box<V> {
+        let value = unsafe { hashmap::hashmap_remove_slot(self.map.ptr, self.slot, self.hash) };
+        assert!(!value.is_null(), "occupied entries always have a value");
+        self.map.len -= 1;
+        unsafe { Box::from_raw(value as *mut V) }
+    }
+}
+
+/// View into a key of a [`HashMap`] that has no value, part of [`Entry`].
+pub struct VacantEntry<'a, V> {
+    /// Hashmap the entry belongs to
+    map: &'a mut HashMap<V>,
+    /// Bytes of the key of the entry, kept around for inserting it
+    key: Vec<u8>,
+    /// Hash of the key computed during the lookup
+    hash: u64,
+}
+
+impl<'a, V> VacantEntry<'a, V> {
+    /// Returns the key of this entry.
+    ///
+    /// # Panics
+    /// Panics if the key is not valid UTF-8, like [`Entry::key`].
+    pub fn key(&self) -> &str {
+        key_str(&self.key)
+    }
+
+    /// Returns the bytes of the key of this entry.
+    pub fn key_bytes(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Take ownership of the key without inserting anything.
+    ///
+    /// # Panics
+    /// Panics if the key is not valid UTF-8, like [`Entry::key`].
+    pub fn into_key(self) -> String {
+        String::from_utf8(self.key).expect("the key of the entry is not valid UTF-8")
+    }
+
+    /// Insert the value for the key, reusing the hash from the lookup, and
+    /// return a mutable reference to it.
+    ///
+    /// # Panics
+    /// Panics if the entry for the key cannot be allocated.
+    pub fn insert(self, value: V) -> &'a mut V {
+        unsafe { or_insert_slot(self.map, &self.key, self.hash, ptr::null_mut(), || value) }
+    }
+}
+
+/// A view into a single key of a [`HashMap`] like [`Entry`], but borrowing the
+/// key instead of copying it, created by [`HashMap::entry_ref`]. Nothing is
+/// allocated unless the key turns out to be vacant and a value is inserted, at
+/// which point the C hashmap copies the key.
+pub struct EntryRef<'a, 'k, V, K: HashMapKey + ?Sized + 'k = str> {
+    /// Hashmap the entry belongs to
+    map: &'a mut HashMap<V>,
+    /// Borrowed key of the entry
+    key: &'k K,
+    /// Bytes of the key, which borrow from it for string and byte keys
+    bytes: K::Bytes<'k>,
+    /// Hash of the key computed during the lookup
+    hash: u64,
+    /// Pointer to the value slot of the key in the C hashmap, or null if the key
+    /// is vacant
+    slot: *mut *mut c_void,
+}
+
+impl<'a, 'k, V, K: HashMapKey + ?Sized> EntryRef<'a, 'k, V, K> {
+    /// Look up the given key in the hashmap, remembering where its value lives.
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte.
+    pub(crate) fn new(map: &'a mut HashMap<V>, key: &'k K) -> Self {
+        let bytes = key
+            .key_bytes()
+            .expect("keys must not contain interior nul bytes");
+        let (hash, slot) = lookup(map, bytes.as_ref());
+        Self {
+            map,
+            key,
+            bytes,
+            hash,
+            slot,
+        }
+    }
+
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &'k K {
+        self.key
+    }
+
+    /// Ensure a value is present by inserting the default if the key is vacant,
+    /// then return a mutable reference to the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Ensure a value is present by inserting the result of the default
+    /// function if the key is vacant, then return a mutable reference to the
+    /// value. The function is only called if the key is vacant.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        unsafe { or_insert_slot(self.map, self.bytes.as_ref(), self.hash, self.slot, default) }
+    }
+
+    /// Call the function on the value if the key is occupied, then return the
+    /// entry for further chaining.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        if !self.slot.is_null() {
+            f(unsafe { &mut *(*self.slot as *mut V) });
+        }
+        self
+    }
+}
+
+impl<'a, V: Default, K: HashMapKey + ?Sized> EntryRef<'a, '_, V, K> {
+    /// Ensure a value is present by inserting the default value if the key is
+    /// vacant, then return a mutable reference to the value. The default value
+    /// is only created if the key is vacant.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+/// Copy the bytes of the given key for an entry to own.
+///
+/// # Panics
+/// Panics if the key is a string containing an interior nul byte.
+fn key_bytes(key: &(impl HashMapKey + ?Sized)) -> Vec<u8> {
+    key.key_bytes()
+        .expect("keys must not contain interior nul bytes")
+        .as_ref()
+        .to_vec()
+}
+
+/// Returns the key of an entry as a string.
+///
+/// # Panics
+/// Panics if the key is not valid UTF-8.
+fn key_str(key: &[u8]) -> &str {
+    str::from_utf8(key).expect("the key of the entry is not valid UTF-8")
+}
+
+/// Look up the given key in the hashmap, returning its hash along with the
+/// pointer to its value slot, or null if the key is vacant.
+fn lookup<V>(map: &HashMap<V>, key: &[u8]) -> (u64, *mut *mut c_void) {
+    let mut hash = 0;
+    let slot = unsafe {
+        hashmap::hashmap_get_slot(map.ptr, key.as_ptr() as *const c_void, key.len(), &mut hash)
+    };
+    (hash, slot)
+}
+
+/// Return a mutable reference to the value behind the slot found for the key,
+/// first inserting the result of the default function if the slot is null.
+/// Inserting may resize the hashmap, so the reference is always taken from the
+/// slot of the new entry, which is only linked in once the resize is done.
+///
+/// # Panics
+/// Panics if the entry for the key cannot be allocated.
+///
+/// # Safety
+/// The slot and hash must come from looking up the key in the hashmap, with no
+/// modification of the hashmap since.
+unsafe fn or_insert_slot<'a, V, F: FnOnce() -> V>(
+    map: &'a mut HashMap<V>,
+    key: &[u8],
+    hash: u64,
+    slot: *mut *mut c_void,
+    default: F,
+) -> &'a mut V {
+    let slot = if slot.is_null() {
+        let value = Box::into_raw(Box::new(default()));
+        let slot = hashmap::hashmap_insert_hashed(
+            map.ptr,
+            key.as_ptr() as *const c_void,
+            key.len(),
+            hash,
+            value as *mut c_void,
+        );
+        if slot.is_null() {
+            drop(Box::from_raw(value));
+            panic!("failed to allocate hashmap entry");
+        }
+        map.len += 1;
+        slot
+    } else {
+        slot
+    };
+    &mut *(*slot as *mut V)
+}
+
+/// Builder for entries of a [`HashMap`] that lets the caller supply the hash of
+/// the key, created by [`HashMap::raw_entry_mut`]. Code that already knows the
+/// hash of a key, for example from [`HashMap::hash_key`], can skip hashing it
+/// again.
+pub struct RawEntryBuilderMut<'a, V> {
+    /// Hashmap the entries are built for
+    map: &'a mut HashMap<V>,
+}
+
+impl<'a, V> RawEntryBuilderMut<'a, V> {
+    /// Create a new raw entry builder for the given hashmap.
+    pub(crate) fn new(map: &'a mut HashMap<V>) -> Self {
+        Self { map }
+    }
+
+    /// Get the entry for the given key, hashing it like [`HashMap::entry`].
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte.
+    pub fn from_key(self, key: &(impl HashMapKey + ?Sized)) -> Entry<'a, V> {
+        Entry::new(self.map, key)
+    }
+
+    /// Get the entry for the given key using the given hash instead of
+    /// computing it. The hash must be the one [`HashMap::hash_key`] returns for
+    /// the key. A mismatched hash does not cause undefined behavior, but the
+    /// key is looked up and inserted in the wrong bucket, so it may not be
+    /// found again and may end up stored twice.
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte.
+    pub fn from_hash(self, hash: u64, key: &(impl HashMapKey + ?Sized)) -> Entry<'a, V> {
+        Entry::with_hash(self.map, key, hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{
+        tests::{constant_hasher, Counted},
+        Entry, HashMap,
+    };
+
+    #[test]
+    fn test_or_insert() {
+        let mut map = HashMap::new();
+        *map.entry("foo").or_insert(0) += 1;
+        *map.entry("foo").or_insert(0) += 1;
+        assert_eq!(map.get("foo"), Some(&2));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "probe-stats")]
+    fn test_and_modify_or_insert_single_lookup() {
+        // With every key in one chain, each lookup takes a probe per key it
+        // passes, so a second lookup would double the count
+        let mut map = HashMap::with_hasher(constant_hasher);
+        for key in ["a", "b", "c"] {
+            map.insert(key, 0);
+        }
+        for key in ["a", "d", "d"] {
+            map.take_probe_count();
+            map.get(key);
+            let get_probes = map.take_probe_count();
+            map.entry(key).and_modify(|v| *v += 1).or_insert(1);
+            assert_eq!(map.take_probe_count(), get_probes);
+        }
+        assert_eq!(map.get("a"), Some(&1));
+        assert_eq!(map.get("d"), Some(&2));
+        assert_eq!(map.len(), 4);
+
+        // Removing through the entry finds the node by address, not by key
+        map.take_probe_count();
+        map.get("b");
+        let get_probes = map.take_probe_count();
+        if let Entry::Occupied(entry) = map.entry("b") {
+            entry.remove();
+        }
+        assert_eq!(map.take_probe_count(), get_probes);
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn test_occupied_and_vacant() {
+        let mut map = HashMap::new();
+        match map.entry("foo") {
+            Entry::Occupied(_) => panic!("the key was never inserted"),
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), "foo");
+                *entry.insert(1) += 1;
+            }
+        }
+        let Entry::Occupied(mut entry) = map.entry("foo") else {
+            panic!("the key was inserted");
+        };
+        assert_eq!(entry.key(), "foo");
+        assert_eq!(*entry.get(), 2);
+        *entry.get_mut() += 1;
+        assert_eq!(entry.insert(4), 3);
+        *entry.into_mut() += 1;
+        assert_eq!(map.get("foo"), Some(&5));
+
+        let Entry::Vacant(entry) = map.entry("bar") else {
+            panic!("the key was never inserted");
+        };
+        assert_eq!(entry.into_key(), "bar");
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_occupied_entry_remove() {
+        let drops = Rc::new(Cell::new(0));
+        let maps = [
+            HashMap::with_hasher(constant_hasher),
+            HashMap::ordered(),
+            HashMap::with_open_addressing(),
+            HashMap::with_arena(),
+            HashMap::builder().inline_keys().build(),
+        ];
+        for mut map in maps {
+            for i in 0..20 {
+                map.insert(&i.to_string(), (i, Counted(drops.clone())));
+            }
+            // Remove from the start, middle and end of chains and probe runs
+            for i in [0, 7, 19, 10, 3] {
+                let Entry::Occupied(entry) = map.entry(&i.to_string()) else {
+                    panic!("the key was inserted");
+                };
+                assert_eq!(entry.remove().0, i);
+                assert!(!map.contains_key(&i.to_string()));
+                assert_eq!(map.validate(), Ok(()));
+            }
+            assert_eq!(map.len(), 15);
+            assert_eq!(map.iter().count(), 15);
+        }
+        assert_eq!(drops.get(), 100);
+    }
+
+    #[test]
+    fn test_or_insert_with() {
+        let mut map = HashMap::new();
+        assert_eq!(*map.entry("foo").or_insert_with(|| 42), 42);
+        assert_eq!(
+            *map.entry("foo").or_insert_with(|| panic!("key is present")),
+            42
+        );
+    }
+
+    #[test]
+    fn test_or_default() {
+        let mut map: HashMap<Vec<i32>> = HashMap::new();
+        for i in 0..10 {
+            map.entry(if i % 2 == 0 { "even" } else { "odd" })
+                .or_default()
+                .push(i);
+        }
+        assert_eq!(map.get("even"), Some(&vec![0, 2, 4, 6, 8]));
+        assert_eq!(map.get("odd"), Some(&vec![1, 3, 5, 7, 9]));
+    }
+
+    #[test]
+    fn test_and_modify() {
+        let mut map = HashMap::new();
+        map.entry("foo").and_modify(|v| *v += 1).or_insert(1);
+        assert_eq!(map.get("foo"), Some(&1));
+        map.entry("foo").and_modify(|v| *v += 1).or_insert(1);
+        assert_eq!(map.get("foo"), Some(&2));
+    }
+
+    #[test]
+    fn test_entry_ref() {
+        let mut map = HashMap::new();
+        let key = String::from("foo");
+        let entry = map.entry_ref(&key);
+        assert_eq!(entry.key(), "foo");
+        *entry.or_insert(0) += 1;
+        drop(key);
+        map.entry_ref("foo").and_modify(|v| *v += 1).or_insert(10);
+        assert_eq!(*map.entry_ref("foo").or_default(), 2);
+        assert_eq!(*map.entry_ref("bar").or_insert_with(|| 3), 3);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_entry_non_string_keys() {
+        let mut map = HashMap::new();
+        *map.entry(&7u32).or_insert(0) += 1;
+        *map.entry(&7u32.to_le_bytes()).or_insert(0) += 1;
+        assert_eq!(map.get(&7u32), Some(&2));
+        let entry = map.entry(b"\xff");
+        assert_eq!(entry.key_bytes(), b"\xff");
+        entry.or_insert(3);
+        let Entry::Occupied(entry) = map.entry(b"\xff") else {
+            panic!("the key was inserted");
+        };
+        assert_eq!(entry.key_bytes(), b"\xff");
+        assert_eq!(*entry.remove(), 3);
+
+        let entry = map.entry_ref(&8u32);
+        assert_eq!(entry.key(), &8);
+        *entry.or_default() += 1;
+        map.entry_ref(&8u32.to_le_bytes()).and_modify(|v| *v += 1);
+        assert_eq!(map.get(&8u32), Some(&2));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "not valid UTF-8")]
+    fn test_entry_key_not_utf8() {
+        let mut map = HashMap::<i32>::new();
+        map.entry(b"\xff").key();
+    }
+
+    #[test]
+    fn test_raw_entry() {
+        let mut map = HashMap::new();
+        let hash = map.hash_key(b"foo");
+        assert_eq!(hash, map.hash_key(b"foo"));
+        *map.raw_entry_mut().from_hash(hash, "foo").or_insert(0) += 1;
+        *map.raw_entry_mut().from_key("foo").or_insert(0) += 1;
+        assert_eq!(map.get("foo"), Some(&2));
+        assert_eq!(map.raw_entry_mut().from_hash(hash, "foo").key(), "foo");
+
+        for i in 0..100 {
+            let key = i.to_string();
+            let hash = map.hash_key(key.as_bytes());
+            map.raw_entry_mut().from_hash(hash, &key).or_insert(i);
+        }
+        for i in 0..100 {
+            assert_eq!(map.get(&i.to_string()), Some(&i));
+        }
+        assert_eq!(map.len(), 101);
+    }
+
+    #[test]
+    fn test_entry_across_resize() {
+        let mut map = HashMap::new();
+        for i in 0..1000 {
+            assert_eq!(*map.entry(&i.to_string()).or_insert(i), i);
+        }
+        assert_eq!(map.len(), 1000);
+        for i in 0..1000 {
+            assert_eq!(map.get(&i.to_string()), Some(&i));
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Errors returned by the fallible [`HashMap`](crate::HashMap) operations.
+
+use core::fmt;
+
+/// Error returned when a hashmap operation cannot be carried out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashMapError {
+    /// The key is a string containing an interior nul byte, which string keys
+    /// are never allowed to contain. Use the byte key methods for such keys
+    /// instead.
+    InvalidKey,
+    /// The entry for a new key could not be allocated.
+    AllocError,
+}
+
+impl fmt::Display for HashMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidKey => f.write_str("key contains an interior nul byte"),
+            Self::AllocError => f.write_str("memory allocation failed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HashMapError {}
+
+/// Error returned by [`HashMap::try_reserve`](crate::HashMap::try_reserve) when
+/// the requested space cannot be reserved. The hashmap is left as it was.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// No number of buckets can hold the requested number of keys.
+    CapacityOverflow,
+    /// The allocator failed to allocate the buckets.
+    AllocError,
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CapacityOverflow => f.write_str("capacity overflow"),
+            Self::AllocError => f.write_str("memory allocation failed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TryReserveError {}
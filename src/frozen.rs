@@ -0,0 +1,100 @@
+//! Immutable snapshot of a [`HashMap`] that can be shared between threads.
+
+use core::fmt::{self, Debug};
+
+use crate::{HashMap, HashMapKey, Iter};
+
+/// [`HashMap`] locked into an immutable form, created by [`HashMap::freeze`].
+/// It only has methods that read the hashmap, so nothing can change it for as
+/// long as it stays frozen, no matter how many threads share it. It is `Sync`
+/// whenever `V` is. Use [`FrozenHashMap::thaw`] to get the mutable hashmap
+/// back.
+pub struct FrozenHashMap<V> {
+    /// Hashmap that is never handed out mutably while frozen
+    map: HashMap<V>,
+}
+
+impl<V> FrozenHashMap<V> {
+    /// Freeze the given hashmap.
+    pub(crate) fn new(map: HashMap<V>) -> Self {
+        Self { map }
+    }
+
+    /// Returns the number of keys stored in the hashmap.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the hashmap contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Get a reference to the value associated with the given key like
+    /// [`HashMap::get`].
+    pub fn get(&self, key: &(impl HashMapKey + ?Sized)) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    /// Returns true if the hashmap contains a value for the given key like
+    /// [`HashMap::contains_key`].
+    pub fn contains_key(&self, key: &(impl HashMapKey + ?Sized)) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Returns an iterator over the key-value pairs of the hashmap like
+    /// [`HashMap::iter`].
+    pub fn iter(&self) -> Iter<'_, V> {
+        self.map.iter()
+    }
+
+    /// Unfreeze the hashmap, making it mutable again.
+    pub fn thaw(self) -> HashMap<V> {
+        self.map
+    }
+}
+
+impl<V: Debug> Debug for FrozenHashMap<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.map.fmt(f)
+    }
+}
+
+impl<'a, V> IntoIterator for &'a FrozenHashMap<V> {
+    type Item = (&'a str, &'a V);
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::HashMap;
+
+    #[test]
+    fn test_freeze() {
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(&i.to_string(), i);
+        }
+        let frozen = map.freeze();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    assert_eq!(frozen.len(), 100);
+                    assert_eq!(frozen.get("42"), Some(&42));
+                    assert!(frozen.contains_key("99"));
+                    assert!(!frozen.contains_key("100"));
+                    assert_eq!(frozen.iter().map(|(_, v)| v).sum::<i32>(), 4950);
+                });
+            }
+        });
+
+        let mut map = frozen.thaw();
+        map.insert("100", 100);
+        assert_eq!(map.len(), 101);
+    }
+}
@@ -1,200 +1,1592 @@
 #include "hashmap.h"
-#include "stdio.h"
 
-// Hashes the given key using the secret key in the HashMap using SipHash-2-4
-// with an 8 byte output and stores the result in hash.
-static void hashmap_hash_key(const HashMap *map, const char *key,
-                             uint64_t *hash) {
+#include <stddef.h>
+
+#ifdef HASHMAP_RUST_ALLOC
+// Allocation functions provided by the Rust wrapper when it is built without
+// std. They forward to the global Rust allocator so that the HashMap does not
+// depend on a hosted libc allocator. They behave like their libc counterparts.
+void *hashmap_rust_malloc(size_t size);
+void *hashmap_rust_calloc(size_t count, size_t size);
+void hashmap_rust_free(void *ptr);
+
+#define HASHMAP_MALLOC hashmap_rust_malloc
+#define HASHMAP_CALLOC hashmap_rust_calloc
+#define HASHMAP_FREE hashmap_rust_free
+#else
+#define HASHMAP_MALLOC malloc
+#define HASHMAP_CALLOC calloc
+#define HASHMAP_FREE free
+#endif
+
+// Size of the first block of an arena. Every following block is twice as large
+// as the one before, up to HASHMAP_ARENA_MAX_BLOCK.
+#define HASHMAP_ARENA_MIN_BLOCK 4096
+#define HASHMAP_ARENA_MAX_BLOCK (1 << 20)
+
+// Block of memory an arena bumps allocations out of. Blocks are chained from
+// the newest to the oldest so they can all be freed together.
+typedef struct HashMapArenaBlock {
+  struct HashMapArenaBlock *next;
+  size_t size;
+  size_t used;
+  max_align_t data[];
+} HashMapArenaBlock;
+
+struct HashMapArena {
+  HashMapArenaBlock *blocks;
+};
+
+// Longest key stored inline right after its entry when a HashMap stores short
+// keys inline.
+#define HASHMAP_INLINE_KEY_MAX 15
+
+// Largest number of buckets whose array still has a size that fits in a
+// size_t, so that computing it cannot wrap around to a small allocation.
+#define HASHMAP_MAX_BUCKETS (SIZE_MAX / sizeof(Entry *))
+
+// Number of bytes of a key lowercased at a time when hashing keys of a case
+// insensitive HashMap.
+#define HASHMAP_FOLD_CHUNK 256
+
+// Returns the given byte with ASCII uppercase letters turned into lowercase.
+static uint8_t hashmap_ascii_lower(uint8_t byte) {
+  return byte >= 'A' && byte <= 'Z' ? byte + ('a' - 'A') : byte;
+}
+
+// Hashes the given key of key_len bytes with its ASCII letters lowercased using
+// SipHash-2-4 and stores the 8 byte output in hash_bytes. The key is lowercased
+// into a buffer on the stack one chunk at a time, and every chunk after the
+// first is hashed with the hash of the chunks before it mixed into the secret
+// key, so no allocation is needed however long the key is.
+static void hashmap_siphash_folded(const HashMap *map, const uint8_t *key,
+                                   size_t key_len, uint8_t hash_bytes[8]) {
+  uint8_t secret[16];
+  memcpy(secret, map->key, sizeof(secret));
+
+  uint8_t chunk[HASHMAP_FOLD_CHUNK];
+  size_t offset = 0;
+  do {
+    size_t chunk_len = key_len - offset;
+    if (chunk_len > sizeof(chunk)) {
+      chunk_len = sizeof(chunk);
+    }
+    for (size_t i = 0; i < chunk_len; i++) {
+      chunk[i] = hashmap_ascii_lower(key[offset + i]);
+    }
+
+    siphash(chunk, chunk_len, secret, hash_bytes, 8);
+    for (size_t i = 0; i < 8; i++) {
+      secret[i] ^= hash_bytes[i];
+    }
+    offset += chunk_len;
+  } while (offset < key_len);
+}
+
+// Hashes the given key of key_len bytes using the custom hasher of the HashMap
+// if it has one, or otherwise using the secret key in the HashMap using
+// SipHash-2-4 with an 8 byte output, and stores the result in hash.
+static void hashmap_hash_key(const HashMap *map, const void *key,
+                             size_t key_len, uint64_t *hash) {
   if (map == NULL || key == NULL || hash == NULL) {
     return;
   }
 
-  uint8_t hash_bytes[8] = {0};
-  siphash(key, strlen(key), map->key, hash_bytes, 8);
+  if (map->hasher != NULL) {
+    *hash = map->hasher(key, key_len);
+    return;
+  }
+
+  uint8_t hash_bytes[8] = {0};
+  if (map->case_insensitive) {
+    hashmap_siphash_folded(map, key, key_len, hash_bytes);
+  } else {
+    siphash(key, key_len, map->key, hash_bytes, 8);
+  }
+
+  *hash = 0;
+  for (size_t i = 0; i < 8; i++) {
+    *hash |= ((uint64_t)hash_bytes[i]) << (i * 8);
+  }
+}
+
+// Returns true if the entry's key is equal to the given key of key_len bytes,
+// ignoring ASCII case if the HashMap is case insensitive.
+static bool hashmap_key_eq(const HashMap *map, const Entry *entry,
+                           const void *key, size_t key_len) {
+#ifdef HASHMAP_PROBE_STATS
+  // Lookups only read the HashMap and may run on several threads at once, so
+  // the counter is the one field they update, and atomically.
+  __atomic_fetch_add(&((HashMap *)map)->probes, 1, __ATOMIC_RELAXED);
+#endif
+  if (entry->key_len != key_len) {
+    return false;
+  }
+  if (!map->case_insensitive) {
+    return memcmp(entry->key, key, key_len) == 0;
+  }
+
+  const uint8_t *entry_key = (const uint8_t *)entry->key;
+  const uint8_t *other_key = key;
+  for (size_t i = 0; i < key_len; i++) {
+    if (hashmap_ascii_lower(entry_key[i]) !=
+        hashmap_ascii_lower(other_key[i])) {
+      return false;
+    }
+  }
+
+  return true;
+}
+
+// Returns the entry in the HashMap for the given key of key_len bytes with the
+// given hash, or NULL if the key does not exist.
+static Entry *hashmap_find(const HashMap *map, const void *key, size_t key_len,
+                           uint64_t hash) {
+  if (map->buckets == 0) {
+    return NULL;
+  }
+  if (map->open_addressing) {
+    // there is always at least one empty slot to end the probe at
+    for (size_t i = hash % map->buckets; map->slots[i].key != NULL;
+         i = (i + 1) % map->buckets) {
+      if (hashmap_key_eq(map, &map->slots[i], key, key_len)) {
+        return &map->slots[i];
+      }
+    }
+
+    return NULL;
+  }
+  if (map->entries == NULL) {
+    return NULL;
+  }
+
+  Entry *entry = map->entries[hash % map->buckets];
+  while (entry != NULL) {
+    if (hashmap_key_eq(map, entry, key, key_len)) {
+      return entry;
+    }
+
+    entry = entry->next;
+  }
+
+  return NULL;
+}
+
+// Returns the slot an open addressing HashMap probes first for the key stored
+// in the given slot.
+static size_t hashmap_slot_home(const HashMap *map, const Entry *slot) {
+  uint64_t hash = 0;
+  hashmap_hash_key(map, slot->key, slot->key_len, &hash);
+  return hash % map->buckets;
+}
+
+// Stores the entry in the first empty slot probed for the given hash among
+// the given number of slots, of which at least one must be empty.
+static Entry *hashmap_slot_place(Entry *slots, size_t buckets, uint64_t hash,
+                                 const Entry *entry) {
+  size_t i = hash % buckets;
+  while (slots[i].key != NULL) {
+    i = (i + 1) % buckets;
+  }
+
+  slots[i] = *entry;
+  return &slots[i];
+}
+
+// Empties slot i of an open addressing HashMap, whose key and value must have
+// been taken care of already. Rather than leaving a tombstone, every entry
+// probed past the slot is shifted back into the gap unless that would move it
+// in front of the slot it is probed from, so lookups never have to skip over
+// removed entries.
+static void hashmap_slot_remove(HashMap *map, size_t i) {
+  size_t hole = i;
+  for (size_t j = (i + 1) % map->buckets; map->slots[j].key != NULL;
+       j = (j + 1) % map->buckets) {
+    // the entry has to stay if its home slot lies after the hole, up to and
+    // including j itself, with the probe wrapping around the end
+    size_t home = hashmap_slot_home(map, &map->slots[j]);
+    bool stays =
+        hole <= j ? hole < home && home <= j : hole < home || home <= j;
+    if (!stays) {
+      map->slots[hole] = map->slots[j];
+      hole = j;
+    }
+  }
+
+  memset(&map->slots[hole], 0, sizeof(map->slots[hole]));
+}
+
+// Allocates size bytes from the arena, suitably aligned for any type, starting
+// a new block if the newest one is too full. Returns NULL if the allocation
+// fails.
+static void *hashmap_arena_alloc(HashMapArena *arena, size_t size) {
+  const size_t align = _Alignof(max_align_t);
+  if (size > SIZE_MAX - sizeof(HashMapArenaBlock) - align) {
+    return NULL;
+  }
+  size = (size + align - 1) / align * align;
+
+  HashMapArenaBlock *block = arena->blocks;
+  if (block == NULL || block->size - block->used < size) {
+    size_t block_size = HASHMAP_ARENA_MIN_BLOCK;
+    if (block != NULL && block->size < HASHMAP_ARENA_MAX_BLOCK) {
+      block_size = block->size * 2;
+    } else if (block != NULL) {
+      block_size = HASHMAP_ARENA_MAX_BLOCK;
+    }
+    if (block_size < size) {
+      block_size = size;
+    }
+
+    HashMapArenaBlock *new_block =
+        HASHMAP_MALLOC(sizeof(*new_block) + block_size);
+    if (new_block == NULL) {
+      return NULL;
+    }
+    new_block->next = block;
+    new_block->size = block_size;
+    new_block->used = 0;
+    arena->blocks = new_block;
+    block = new_block;
+  }
+
+  void *ptr = (unsigned char *)block->data + block->used;
+  block->used += size;
+  return ptr;
+}
+
+// Frees every block of the arena except the newest, which is the largest, and
+// empties that one so it can be reused.
+static void hashmap_arena_reset(HashMapArena *arena) {
+  HashMapArenaBlock *block = arena->blocks;
+  if (block == NULL) {
+    return;
+  }
+
+  HashMapArenaBlock *older = block->next;
+  while (older != NULL) {
+    HashMapArenaBlock *next = older->next;
+    HASHMAP_FREE(older);
+    older = next;
+  }
+  block->next = NULL;
+  block->used = 0;
+}
+
+// Frees the arena along with all of its blocks.
+static void hashmap_arena_free(HashMapArena *arena) {
+  if (arena == NULL) {
+    return;
+  }
+
+  HashMapArenaBlock *block = arena->blocks;
+  while (block != NULL) {
+    HashMapArenaBlock *next = block->next;
+    HASHMAP_FREE(block);
+    block = next;
+  }
+  HASHMAP_FREE(arena);
+}
+
+// Allocates size bytes for an entry or key, from the arena if there is one and
+// from the heap otherwise. Returns NULL if the allocation fails.
+static void *hashmap_node_alloc(HashMapArena *arena, size_t size) {
+  if (arena != NULL) {
+    return hashmap_arena_alloc(arena, size);
+  }
+
+  return HASHMAP_MALLOC(size);
+}
+
+// Frees an entry or key allocated by hashmap_node_alloc. Memory from an arena is
+// left alone, since it is only reclaimed with the whole arena.
+static void hashmap_node_free(HashMapArena *arena, void *ptr) {
+  if (arena == NULL) {
+    HASHMAP_FREE(ptr);
+  }
+}
+
+// Copies the given key of key_len bytes into a new allocation from the arena,
+// or from the heap if arena is NULL, with a trailing nul byte so that string
+// keys can still be read as C strings. Returns NULL if the allocation fails.
+static char *hashmap_key_dup(HashMapArena *arena, const void *key,
+                             size_t key_len) {
+  char *copy = hashmap_node_alloc(arena, key_len + 1);
+  if (copy == NULL) {
+    return NULL;
+  }
+
+  memcpy(copy, key, key_len);
+  copy[key_len] = '\0';
+  return copy;
+}
+
+// Returns true if the key of the entry is stored inline right after it rather
+// than in an allocation of its own.
+static bool hashmap_key_is_inline(const HashMap *map, const Entry *entry) {
+  return map->inline_keys && entry->key_len <= HASHMAP_INLINE_KEY_MAX;
+}
+
+// Allocates a new entry for the given key of key_len bytes and copies the key
+// with a trailing nul byte, inline right after the entry if the HashMap stores
+// short keys inline and the key is short enough. Only the key and its length
+// are set. Returns NULL if an allocation fails.
+static Entry *hashmap_entry_new(HashMap *map, const void *key,
+                                size_t key_len) {
+  Entry *entry = NULL;
+  if (map->inline_keys && key_len <= HASHMAP_INLINE_KEY_MAX) {
+    entry = hashmap_node_alloc(map->arena, sizeof(*entry) + key_len + 1);
+    if (entry == NULL) {
+      return NULL;
+    }
+
+    entry->key = (char *)(entry + 1);
+    memcpy(entry->key, key, key_len);
+    entry->key[key_len] = '\0';
+  } else {
+    entry = hashmap_node_alloc(map->arena, sizeof(*entry));
+    if (entry == NULL) {
+      return NULL;
+    }
+
+    entry->key = hashmap_key_dup(map->arena, key, key_len);
+    if (entry->key == NULL) {
+      hashmap_node_free(map->arena, entry);
+      return NULL;
+    }
+  }
+
+  entry->key_len = key_len;
+  return entry;
+}
+
+// Frees an entry that has been unlinked from the HashMap, along with its key
+// unless free_key is false because the caller took ownership of the key.
+static void hashmap_entry_free(HashMap *map, Entry *entry, bool free_key) {
+  if (free_key && !hashmap_key_is_inline(map, entry)) {
+    hashmap_node_free(map->arena, entry->key);
+  }
+  hashmap_node_free(map->arena, entry);
+}
+
+// Links a newly created entry into the insertion order of the HashMap as its
+// newest entry, or clears its order pointers if the HashMap is not ordered.
+static void hashmap_order_append(HashMap *map, Entry *entry) {
+  entry->order_next = NULL;
+  if (!map->ordered) {
+    entry->order_prev = NULL;
+    return;
+  }
+
+  entry->order_prev = map->order_tail;
+  if (map->order_tail != NULL) {
+    map->order_tail->order_next = entry;
+  } else {
+    map->order_head = entry;
+  }
+  map->order_tail = entry;
+}
+
+// Unlinks an entry that is about to be removed from the insertion order of the
+// HashMap. Does nothing if the HashMap is not ordered.
+static void hashmap_order_unlink(HashMap *map, Entry *entry) {
+  if (!map->ordered) {
+    return;
+  }
+
+  if (entry->order_prev != NULL) {
+    entry->order_prev->order_next = entry->order_next;
+  } else {
+    map->order_head = entry->order_next;
+  }
+  if (entry->order_next != NULL) {
+    entry->order_next->order_prev = entry->order_prev;
+  } else {
+    map->order_tail = entry->order_prev;
+  }
+}
+
+// Stores a new entry for the given key of key_len bytes, which must not exist
+// yet, with the given hash and value in an open addressing HashMap, copying the
+// key like hashmap_entry_new. Returns NULL if the key cannot be copied or if
+// storing the entry would fill the last empty slot, which probes rely on to
+// end.
+static Entry *hashmap_slot_insert(HashMap *map, const void *key,
+                                  size_t key_len, uint64_t hash, void *value) {
+  if (map->len + 1 >= map->buckets) {
+    return NULL;
+  }
+
+  Entry entry = {0};
+  entry.key = hashmap_key_dup(map->arena, key, key_len);
+  if (entry.key == NULL) {
+    return NULL;
+  }
+  entry.key_len = key_len;
+  entry.value = value;
+
+  map->len++;
+  return hashmap_slot_place(map->slots, map->buckets, hash, &entry);
+}
+
+// Resizes the HashMap to the given, non-zero number of buckets. The existing
+// entries are relinked into the new buckets rather than reallocated, so
+// pointers to them stay valid, except in open addressing HashMaps, which move
+// their entries into the new slots. If there are more than HASHMAP_MAX_BUCKETS
+// new buckets, HASHMAP_CAPACITY_OVERFLOW is returned, and if they cannot be
+// allocated, HASHMAP_ALLOC_FAILED is, leaving the HashMap unchanged in both
+// cases. Otherwise the resize callback of the HashMap is called once it is
+// done.
+static HashMapStatus hashmap_resize(HashMap *map, size_t new_buckets) {
+  if (map == NULL || new_buckets == 0) {
+    return HASHMAP_OK;
+  }
+  if (new_buckets > HASHMAP_MAX_BUCKETS) {
+    return HASHMAP_CAPACITY_OVERFLOW;
+  }
+
+  if (map->open_addressing) {
+    Entry *new_slots = HASHMAP_CALLOC(new_buckets, sizeof(*new_slots));
+    if (new_slots == NULL) {
+      return HASHMAP_ALLOC_FAILED;
+    }
+
+    for (size_t i = 0; i < map->buckets; i++) {
+      if (map->slots[i].key != NULL) {
+        uint64_t hash = 0;
+        hashmap_hash_key(map, map->slots[i].key, map->slots[i].key_len, &hash);
+        (void)hashmap_slot_place(new_slots, new_buckets, hash, &map->slots[i]);
+      }
+    }
+
+    HASHMAP_FREE(map->slots);
+    map->slots = new_slots;
+  } else {
+    Entry **new_entries = HASHMAP_CALLOC(new_buckets, sizeof(*new_entries));
+    if (new_entries == NULL) {
+      return HASHMAP_ALLOC_FAILED;
+    }
+
+    for (size_t i = 0; i < map->buckets; i++) {
+      Entry *entry = map->entries[i];
+      while (entry != NULL) {
+        Entry *next = entry->next;
+
+        uint64_t hash = 0;
+        hashmap_hash_key(map, entry->key, entry->key_len, &hash);
+        size_t bucket = hash % new_buckets;
+        entry->next = new_entries[bucket];
+        new_entries[bucket] = entry;
+
+        entry = next;
+      }
+    }
+
+    HASHMAP_FREE(map->entries);
+    map->entries = new_entries;
+  }
+
+  size_t old_buckets = map->buckets;
+  map->buckets = new_buckets;
+  if (map->on_resize != NULL) {
+    map->on_resize(map->on_resize_data, old_buckets, new_buckets);
+  }
+  return HASHMAP_OK;
+}
+
+// Grows the HashMap to the given number of buckets. Will not shrink the
+// HashMap. Returns the status of the resize like hashmap_resize.
+static HashMapStatus hashmap_grow(HashMap *map, size_t new_buckets) {
+  if (map == NULL || map->buckets >= new_buckets) {
+    return HASHMAP_OK;
+  }
+
+  return hashmap_resize(map, new_buckets);
+}
+
+// Returns the number of keys that fit in the given number of buckets without
+// the load factor exceeding the maximum load factor of the HashMap.
+static size_t hashmap_capacity_for(const HashMap *map, size_t buckets) {
+  return (size_t)((double)buckets * map->max_load_factor);
+}
+
+// Prime numbers of buckets used by HashMaps created with
+// hashmap_with_prime_buckets, each roughly sqrt(2) times the one before.
+static const uint64_t hashmap_primes[] = {
+    11u, 17u, 23u, 37u, 47u, 67u, 97u, 131u, 181u, 257u, 367u, 521u, 727u,
+    1031u, 1451u, 2053u, 2897u, 4099u, 5801u, 8209u, 11587u, 16411u, 23173u,
+    32771u, 46349u, 65537u, 92681u, 131101u, 185363u, 262147u, 370759u, 524309u,
+    741457u, 1048583u, 1482919u, 2097169u, 2965847u, 4194319u, 5931641u,
+    8388617u, 11863289u, 16777259u, 23726569u, 33554467u, 47453149u, 67108879u,
+    94906297u, 134217757u, 189812533u, 268435459u, 379625083u, 536870923u,
+    759250133u, 1073741827u, 1518500279u, 2147483659u, 3037000507u, 4294967311u,
+    6074001001u, 8589934609u, 12148002047u, 17179869209u, 24296004011u,
+    34359738421u, 48592008053u, 68719476767u, 97184016049u, 137438953481u,
+    194368032011u, 274877906951u, 388736063999u, 549755813911u, 777472128049u,
+    1099511627791u, 1554944255989u, 2199023255579u, 3109888512037u,
+    4398046511119u, 6219777023959u, 8796093022237u, 12439554047911u,
+    17592186044423u, 24879108095833u, 35184372088891u, 49758216191633u,
+    70368744177679u, 99516432383281u, 140737488355333u, 199032864766447u,
+    281474976710677u, 398065729532981u, 562949953421381u, 796131459065743u,
+    1125899906842679u, 1592262918131449u, 2251799813685269u, 3184525836262943u,
+    4503599627370517u, 6369051672525833u, 9007199254740997u, 12738103345051607u,
+    18014398509482143u, 25476206690103097u, 36028797018963971u,
+    50952413380206277u, 72057594037928017u, 101904826760412407u,
+    144115188075855881u, 203809653520824899u, 288230376151711813u,
+    407619307041649457u, 576460752303423619u, 815238614083298939u,
+    1152921504606847009u, 1630477228166597791u, 2305843009213693967u,
+    3260954456333195593u, 4611686018427388039u, 6521908912666391129u,
+};
+
+// Number of prime numbers of buckets in hashmap_primes.
+#define HASHMAP_PRIMES_LEN (sizeof(hashmap_primes) / sizeof(hashmap_primes[0]))
+
+// Returns true if n is a prime number.
+static bool hashmap_is_prime(size_t n) {
+  if (n < 2) {
+    return false;
+  }
+  if (n % 2 == 0) {
+    return n == 2;
+  }
+
+  for (size_t d = 3; d <= n / d; d += 2) {
+    if (n % d == 0) {
+      return false;
+    }
+  }
+
+  return true;
+}
+
+// Returns the smallest prime number of buckets from hashmap_primes that is at
+// least the given number of buckets, or the largest one that fits in a size_t.
+static size_t hashmap_prime_at_least(size_t buckets) {
+  size_t prime = 0;
+  for (size_t i = 0; i < HASHMAP_PRIMES_LEN && hashmap_primes[i] <= SIZE_MAX;
+       i++) {
+    prime = (size_t)hashmap_primes[i];
+    if (prime >= buckets) {
+      break;
+    }
+  }
+
+  return prime;
+}
+
+// Returns the smallest power of two number of buckets, but at least 8, that
+// holds the given number of keys without the load factor exceeding the maximum
+// load factor of the HashMap. If the HashMap uses prime numbers of buckets,
+// returns the smallest one from hashmap_primes instead.
+static size_t hashmap_buckets_for(const HashMap *map, size_t capacity) {
+  if (map->prime_buckets) {
+    size_t buckets = 0;
+    for (size_t i = 0; i < HASHMAP_PRIMES_LEN && hashmap_primes[i] <= SIZE_MAX;
+         i++) {
+      buckets = (size_t)hashmap_primes[i];
+      if (hashmap_capacity_for(map, buckets) >= capacity) {
+        break;
+      }
+    }
+
+    return buckets;
+  }
+
+  size_t buckets = 8;
+  while (hashmap_capacity_for(map, buckets) < capacity &&
+         buckets <= SIZE_MAX / 2) {
+    buckets *= 2;
+  }
+
+  return buckets;
+}
+
+// Returns the number of buckets to grow to from the given number of buckets,
+// which is at least one more, scaled by the growth factor of the HashMap and
+// rounded up.
+static size_t hashmap_grown_buckets(const HashMap *map, size_t buckets) {
+  double grown = (double)buckets * map->growth_factor;
+  if (grown >= (double)SIZE_MAX) {
+    return SIZE_MAX;
+  }
+
+  size_t new_buckets = (size_t)grown;
+  if ((double)new_buckets < grown) {
+    new_buckets++;
+  }
+
+  return new_buckets > buckets ? new_buckets : buckets + 1;
+}
+
+// If the HashMap has no buckets, sets the number of buckets to 8. If the load
+// factor would exceed the maximum load factor, grows the number of buckets by
+// the growth factor as many times as it takes to fit one more key. HashMaps
+// using prime numbers of buckets then round up to the next prime in
+// hashmap_primes.
+static void hashmap_grow_if_needed(HashMap *map) {
+  if (map == NULL) {
+    return;
+  }
+
+  if (map->buckets == 0) {
+    (void)hashmap_grow(map, map->prime_buckets ? (size_t)hashmap_primes[0] : 8);
+    return;
+  }
+
+  size_t buckets = map->buckets;
+  if (map->len < hashmap_capacity_for(map, buckets)) {
+    return;
+  }
+
+  while (map->len >= hashmap_capacity_for(map, buckets) &&
+         buckets < SIZE_MAX) {
+    buckets = hashmap_grown_buckets(map, buckets);
+  }
+  if (map->prime_buckets) {
+    buckets = hashmap_prime_at_least(buckets);
+  }
+
+  (void)hashmap_grow(map, buckets);
+}
+
+// Creates a new HashMap with 0 buckets. Will be resized to 8 buckets when the
+// first entry is added. Keys are hashed with the given 16 byte secret key,
+// which should be random to protect against collision attacks. If key is NULL,
+// an all-zero key is used.
+HashMap *hashmap_new(const uint8_t key[16]) {
+  HashMap *map = HASHMAP_CALLOC(1, sizeof(*map));
+
+  if (map == NULL) {
+    return NULL;
+  }
+
+  map->len = 0;
+  map->buckets = 0;
+  map->entries = NULL;
+
+  map->max_load_factor = 0.75;
+  map->growth_factor = 2;
+  if (key != NULL) {
+    memcpy(map->key, key, sizeof(map->key));
+  }
+
+  return map;
+}
+
+// Creates a new HashMap with the given number of buckets. The secret key is
+// handled as in hashmap_new. Keys are placed by taking their hash modulo the
+// number of buckets, so any number works, and 0 buckets allocates nothing,
+// giving the same HashMap as hashmap_new. Returns NULL if the buckets cannot be
+// allocated, including when the size of their array would not fit in a size_t.
+HashMap *hashmap_with_buckets(size_t buckets, const uint8_t key[16]) {
+  if (buckets > HASHMAP_MAX_BUCKETS) {
+    return NULL;
+  }
+
+  HashMap *map = hashmap_new(key);
+
+  if (map == NULL) {
+    return NULL;
+  }
+
+  if (buckets == 0) {
+    return map;
+  }
+
+  map->entries = HASHMAP_CALLOC(buckets, sizeof(*(map->entries)));
+  if (map->entries == NULL) {
+    HASHMAP_FREE(map);
+    return NULL;
+  }
+  map->buckets = buckets;
+
+  return map;
+}
+
+// Creates a new HashMap whose number of buckets is always a prime number from
+// hashmap_primes, starting with the smallest one that is at least min_buckets.
+// Prime numbers of buckets spread keys more evenly when the hash function is
+// weak. The secret key is handled as in hashmap_new. Returns NULL like
+// hashmap_with_buckets, or if no prime in the table is large enough.
+HashMap *hashmap_with_prime_buckets(size_t min_buckets, const uint8_t key[16]) {
+  size_t buckets = hashmap_prime_at_least(min_buckets);
+  if (buckets < min_buckets) {
+    return NULL;
+  }
+
+  HashMap *map = hashmap_with_buckets(buckets, key);
+  if (map == NULL) {
+    return NULL;
+  }
+
+  map->prime_buckets = true;
+  return map;
+}
+
+// Sets the function used to hash keys instead of SipHash, or restores SipHash
+// if hasher is NULL. Since existing keys would not be rehashed, the hasher can
+// only be changed while the HashMap is empty. Returns true if it was changed.
+bool hashmap_set_hasher(HashMap *map, HashMapHasher hasher) {
+  if (map == NULL || map->len != 0) {
+    return false;
+  }
+
+  map->hasher = hasher;
+  return true;
+}
+
+// Makes the HashMap hash and compare keys with their ASCII letters lowercased,
+// so that keys differing only in ASCII case are treated as the same key, or
+// restores exact comparison if case_insensitive is false. Other bytes are
+// compared as they are. Stored keys keep the case they were first inserted
+// with. A custom hasher receives keys unchanged and must itself ignore ASCII
+// case. Like the hasher, this can only be changed while the HashMap is empty.
+// Returns true if it was changed.
+bool hashmap_set_case_insensitive(HashMap *map, bool case_insensitive) {
+  if (map == NULL || map->len != 0) {
+    return false;
+  }
+
+  map->case_insensitive = case_insensitive;
+  return true;
+}
+
+// Makes the HashMap allocate its entries and their keys from an arena instead
+// of one by one. Removing an entry no longer frees its memory, which is only
+// reclaimed once the HashMap is cleared or freed, so this suits HashMaps that
+// are filled and then freed as a whole. Can only be enabled while the HashMap
+// is empty and never disabled again. Returns true if the HashMap uses an arena
+// afterwards.
+bool hashmap_use_arena(HashMap *map) {
+  if (map == NULL) {
+    return false;
+  }
+  if (map->arena != NULL) {
+    return true;
+  }
+  if (map->len != 0) {
+    return false;
+  }
+
+  map->arena = HASHMAP_CALLOC(1, sizeof(*map->arena));
+  return map->arena != NULL;
+}
+
+// Makes the HashMap store keys of at most 15 bytes inline, in the same
+// allocation right after their entry, instead of in an allocation of their own.
+// Comparing short keys then reads memory next to the entry rather than chasing
+// a pointer elsewhere on the heap. Longer keys are stored as usual. Can only be
+// changed while the HashMap is empty and not enabled for open addressing
+// HashMaps. Returns true if it was changed.
+bool hashmap_set_inline_keys(HashMap *map, bool inline_keys) {
+  if (map == NULL || map->len != 0 || (inline_keys && map->open_addressing)) {
+    return false;
+  }
+
+  map->inline_keys = inline_keys;
+  return true;
+}
+
+// Makes the HashMap remember the order its keys were inserted in, so that
+// iterating over it and hashmap_any_entry follow that order. Replacing the
+// value of an existing key keeps its position, while removing a key and
+// inserting it again moves it to the end. Every entry carries the two order
+// pointers either way, so this only costs the time to maintain them. Can only
+// be changed while the HashMap is empty and not enabled for open addressing
+// HashMaps. Returns true if it was changed.
+bool hashmap_set_ordered(HashMap *map, bool ordered) {
+  if (map == NULL || map->len != 0 || (ordered && map->open_addressing)) {
+    return false;
+  }
+
+  map->ordered = ordered;
+  map->order_head = NULL;
+  map->order_tail = NULL;
+  return true;
+}
+
+// Makes the HashMap store its entries directly in an array of slots and
+// resolve collisions by linear probing instead of chaining, so that looking up
+// a key walks neighbouring slots rather than following pointers. Removing an
+// entry shifts the entries probed past it back instead of leaving a tombstone.
+// Can only be enabled while the HashMap is empty, neither ordered nor storing
+// keys inline, and never disabled again. Returns true if the HashMap uses open
+// addressing afterwards.
+bool hashmap_use_open_addressing(HashMap *map) {
+  if (map == NULL) {
+    return false;
+  }
+  if (map->open_addressing) {
+    return true;
+  }
+  if (map->len != 0 || map->ordered || map->inline_keys) {
+    return false;
+  }
+
+  // the empty buckets are traded for as many empty slots
+  if (map->buckets > 0) {
+    map->slots = HASHMAP_CALLOC(map->buckets, sizeof(*map->slots));
+    if (map->slots == NULL) {
+      return false;
+    }
+  }
+  HASHMAP_FREE(map->entries);
+  map->entries = NULL;
+  map->open_addressing = true;
+  return true;
+}
+
+// Sets the function called with data and the old and new number of buckets
+// whenever the HashMap has finished resizing, whether it grew on insertion,
+// was reserved, or shrank. Passing NULL as callback removes it, which is the
+// default.
+void hashmap_set_resize_callback(HashMap *map, HashMapResizeCallback callback,
+                                 void *data) {
+  if (map == NULL) {
+    return;
+  }
+
+  map->on_resize = callback;
+  map->on_resize_data = data;
+}
+
+// Sets the load factor the HashMap may reach before it grows. The factor must
+// be at least 0.1 and less than 1. If the HashMap already exceeds the new
+// maximum, it grows right away. Returns true if the factor was changed.
+bool hashmap_set_max_load_factor(HashMap *map, double factor) {
+  if (map == NULL || !(factor >= 0.1 && factor < 1)) {
+    return false;
+  }
+
+  map->max_load_factor = factor;
+  if ((map->entries != NULL || map->slots != NULL) &&
+      map->len > hashmap_capacity_for(map, map->buckets)) {
+    (void)hashmap_grow(map, hashmap_buckets_for(map, map->len));
+  }
+
+  return true;
+}
+
+// Sets the factor the number of buckets is multiplied by whenever the HashMap
+// grows on insertion. The factor must be greater than 1 and finite. Does not
+// affect hashmap_reserve or hashmap_reserve_exact, which grow straight to the
+// number of buckets they need. Returns true if the factor was changed.
+bool hashmap_set_growth_factor(HashMap *map, double factor) {
+  if (map == NULL || !(factor > 1 && factor <= (double)SIZE_MAX)) {
+    return false;
+  }
+
+  map->growth_factor = factor;
+  return true;
+}
+
+// Returns the number of keys stored in the HashMap.
+size_t hashmap_len(const HashMap *map) {
+  if (map == NULL) {
+    return 0;
+  }
+
+  return map->len;
+}
+
+// Returns the number of buckets allocated by the HashMap.
+size_t hashmap_bucket_count(const HashMap *map) {
+  if (map == NULL) {
+    return 0;
+  }
+
+  return map->buckets;
+}
+
+// Returns the number of keys the HashMap can hold before it grows.
+size_t hashmap_capacity(const HashMap *map) {
+  if (map == NULL) {
+    return 0;
+  }
+
+  return hashmap_capacity_for(map, map->buckets);
+}
+
+// Returns the ratio of keys to buckets in the HashMap, or 0 if it has no
+// buckets.
+double hashmap_load_factor(const HashMap *map) {
+  if (map == NULL) {
+    return 0;
+  }
+
+  if (map->buckets == 0) {
+    return 0;
+  }
+
+  return (double)map->len / map->buckets;
+}
+
+// Fills in stats by walking every bucket of the HashMap and measuring the length
+// of its chain.
+void hashmap_stats(const HashMap *map, HashMapStats *stats) {
+  if (map == NULL || stats == NULL) {
+    return;
+  }
+
+  stats->buckets = map->buckets;
+  stats->empty_buckets = map->buckets;
+  stats->max_chain_len = 0;
+  stats->mean_chain_len = 0;
+
+  if (map->open_addressing) {
+    size_t probes = 0;
+    for (size_t i = 0; i < map->buckets; i++) {
+      if (map->slots[i].key == NULL) {
+        continue;
+      }
+
+      size_t home = hashmap_slot_home(map, &map->slots[i]);
+      size_t chain_len = (i + map->buckets - home) % map->buckets + 1;
+      stats->empty_buckets--;
+      probes += chain_len;
+      if (chain_len > stats->max_chain_len) {
+        stats->max_chain_len = chain_len;
+      }
+    }
+
+    if (map->len > 0) {
+      stats->mean_chain_len = (double)probes / map->len;
+    }
+    return;
+  }
+  if (map->entries == NULL) {
+    return;
+  }
+
+  size_t entries = 0;
+  for (size_t i = 0; i < map->buckets; i++) {
+    size_t chain_len = 0;
+    for (Entry *entry = map->entries[i]; entry != NULL; entry = entry->next) {
+      chain_len++;
+    }
+
+    if (chain_len > 0) {
+      stats->empty_buckets--;
+      entries += chain_len;
+    }
+    if (chain_len > stats->max_chain_len) {
+      stats->max_chain_len = chain_len;
+    }
+  }
+
+  size_t occupied = map->buckets - stats->empty_buckets;
+  if (occupied > 0) {
+    stats->mean_chain_len = (double)entries / occupied;
+  }
+}
+
+// Returns an estimate of the number of bytes allocated by the HashMap itself:
+// the HashMap struct, its bucket array, and for every entry the Entry node and
+// the copy of its key including the trailing nul byte. Values are not counted
+// since they are owned by the caller, and neither is the bookkeeping overhead
+// of the allocator. Walks every entry to add up the key lengths. For a HashMap
+// using an arena, the blocks of the arena are counted in place of the entries.
+// An open addressing HashMap counts its slots in place of the bucket array and
+// the Entry nodes.
+size_t hashmap_memory_usage(const HashMap *map) {
+  if (map == NULL) {
+    return 0;
+  }
+
+  size_t usage = sizeof(*map);
+  if (map->arena != NULL) {
+    usage += sizeof(*map->arena);
+    for (const HashMapArenaBlock *block = map->arena->blocks; block != NULL;
+         block = block->next) {
+      usage += sizeof(*block) + block->size;
+    }
+  }
+  if (map->open_addressing) {
+    usage += map->buckets * sizeof(*map->slots);
+    for (size_t i = 0; i < map->buckets && map->arena == NULL; i++) {
+      if (map->slots[i].key != NULL) {
+        usage += map->slots[i].key_len + 1;
+      }
+    }
+    return usage;
+  }
+  if (map->entries == NULL) {
+    return usage;
+  }
+
+  usage += map->buckets * sizeof(*map->entries);
+  if (map->arena != NULL) {
+    return usage;
+  }
+  for (size_t i = 0; i < map->buckets; i++) {
+    for (Entry *entry = map->entries[i]; entry != NULL; entry = entry->next) {
+      usage += sizeof(*entry) + entry->key_len + 1;
+    }
+  }
+
+  return usage;
+}
+
+// Checks the internal invariants of the HashMap by walking every entry: that
+// the length matches the number of entries, that every entry sits where a
+// lookup of its key would find it, that no chain is cyclic, that an open
+// addressing HashMap keeps an empty slot, and that the order list of an
+// ordered HashMap links every entry back and forth. Returns NULL if they hold,
+// or otherwise a static string describing the first one found broken. The
+// load factor can exceed the maximum after a failed resize, so only the
+// factors themselves are checked. Meant for tests and fuzzers.
+const char *hashmap_validate(const HashMap *map) {
+  if (map == NULL) {
+    return "the HashMap is NULL";
+  }
+  if (!(map->max_load_factor >= 0.1 && map->max_load_factor < 1) ||
+      !(map->growth_factor > 1)) {
+    return "the maximum load factor or growth factor is out of range";
+  }
+
+  size_t count = 0;
+  if (map->open_addressing) {
+    if (map->entries != NULL || (map->buckets > 0 && map->slots == NULL)) {
+      return "an open addressing HashMap has buckets instead of slots";
+    }
+    for (size_t i = 0; i < map->buckets; i++) {
+      if (map->slots[i].key == NULL) {
+        continue;
+      }
+      // a lookup stops at the first empty slot after the home slot
+      for (size_t j = hashmap_slot_home(map, &map->slots[i]); j != i;
+           j = (j + 1) % map->buckets) {
+        if (map->slots[j].key == NULL) {
+          return "an entry is cut off from its home slot by an empty slot";
+        }
+      }
+      count++;
+    }
+    if (map->buckets > 0 && count >= map->buckets) {
+      return "no slot of the open addressing HashMap is empty";
+    }
+  } else {
+    if (map->slots != NULL || (map->buckets > 0 && map->entries == NULL)) {
+      return "a chaining HashMap has slots instead of buckets";
+    }
+    for (size_t i = 0; i < map->buckets; i++) {
+      for (const Entry *entry = map->entries[i]; entry != NULL;
+           entry = entry->next) {
+        // a cyclic chain never ends, so it soon passes the length
+        if (++count > map->len) {
+          return "the chains hold more entries than the length or are cyclic";
+        }
+        if (entry->key == NULL) {
+          return "an entry has no key";
+        }
+        uint64_t hash = 0;
+        hashmap_hash_key(map, entry->key, entry->key_len, &hash);
+        if (hash % map->buckets != i) {
+          return "an entry is in the chain of another bucket than its key's";
+        }
+      }
+    }
+  }
+  if (count != map->len) {
+    return "the length does not match the number of entries";
+  }
+
+  if (!map->ordered) {
+    if (map->order_head != NULL || map->order_tail != NULL) {
+      return "an unordered HashMap has an order list";
+    }
+    return NULL;
+  }
+  count = 0;
+  const Entry *prev = NULL;
+  for (const Entry *entry = map->order_head; entry != NULL;
+       entry = entry->order_next) {
+    if (++count > map->len) {
+      return "the order list holds more entries than the length or is cyclic";
+    }
+    if (entry->order_prev != prev) {
+      return "an entry of the order list does not link back to the previous";
+    }
+    prev = entry;
+  }
+  if (prev != map->order_tail || count != map->len) {
+    return "the order list does not hold every entry";
+  }
+
+  return NULL;
+}
+
+// Grows the HashMap once so that additional more keys fit without the load
+// factor exceeding the maximum load factor, rehashing the existing entries a
+// single time. Never shrinks the HashMap. Returns HASHMAP_CAPACITY_OVERFLOW if
+// no number of buckets can hold that many keys and HASHMAP_ALLOC_FAILED if the
+// buckets cannot be allocated, leaving the HashMap unchanged in both cases.
+HashMapStatus hashmap_reserve(HashMap *map, size_t additional) {
+  if (map == NULL) {
+    return HASHMAP_OK;
+  }
+
+  size_t capacity = map->len + additional;
+  if (capacity < map->len) {
+    return HASHMAP_CAPACITY_OVERFLOW;
+  }
+
+  size_t buckets = hashmap_buckets_for(map, capacity);
+  if (hashmap_capacity_for(map, buckets) < capacity) {
+    return HASHMAP_CAPACITY_OVERFLOW;
+  }
+
+  return hashmap_grow(map, buckets);
+}
+
+// Grows the HashMap once to exactly the fewest buckets that fit additional
+// more keys without the load factor exceeding the maximum load factor, even if
+// that is not a power of two. HashMaps using prime numbers of buckets round up
+// to the next prime number rather than to one from their table. Later growth
+// on insertion follows the growth factor as usual. Never shrinks the HashMap
+// and returns the same statuses as hashmap_reserve.
+HashMapStatus hashmap_reserve_exact(HashMap *map, size_t additional) {
+  if (map == NULL) {
+    return HASHMAP_OK;
+  }
+
+  size_t capacity = map->len + additional;
+  if (capacity < map->len) {
+    return HASHMAP_CAPACITY_OVERFLOW;
+  }
 
-  *hash = 0;
-  for (size_t i = 0; i < 8; i++) {
-    *hash |= ((uint64_t)hash_bytes[i]) << (i * 8);
+  double exact = (double)capacity / map->max_load_factor;
+  if (exact >= (double)SIZE_MAX) {
+    return HASHMAP_CAPACITY_OVERFLOW;
+  }
+
+  // Dividing in floating point may be off by one either way, so settle on the
+  // exact number of buckets by checking the neighbours.
+  size_t buckets = (size_t)exact;
+  while (hashmap_capacity_for(map, buckets) < capacity) {
+    if (buckets == SIZE_MAX) {
+      return HASHMAP_CAPACITY_OVERFLOW;
+    }
+    buckets++;
   }
+  while (buckets > 1 && hashmap_capacity_for(map, buckets - 1) >= capacity) {
+    buckets--;
+  }
+
+  if (map->prime_buckets) {
+    while (!hashmap_is_prime(buckets)) {
+      if (buckets == SIZE_MAX) {
+        return HASHMAP_CAPACITY_OVERFLOW;
+      }
+      buckets++;
+    }
+  }
+
+  return hashmap_grow(map, buckets);
 }
 
-// Grows the HashMap to the given number of buckets. Will not shrink the
-// HashMap.
-static void hashmap_grow(HashMap *map, size_t new_buckets) {
-  if (map == NULL || map->buckets >= new_buckets) {
+// Shrinks the HashMap to the smallest power of two number of buckets, but at
+// least 8, that holds its keys without the load factor exceeding the maximum
+// load factor. HashMaps using prime numbers of buckets shrink to the smallest
+// fitting prime from their table instead. Never grows the HashMap, so one
+// without buckets stays that way.
+void hashmap_shrink(HashMap *map) {
+  hashmap_shrink_to(map, 0);
+}
+
+// Shrinks the HashMap like hashmap_shrink, but keeps enough buckets to hold at
+// least min_capacity keys without the load factor exceeding the maximum load
+// factor. If the HashMap holds more keys than that, it shrinks only as far as
+// its keys allow. Never grows the HashMap.
+void hashmap_shrink_to(HashMap *map, size_t min_capacity) {
+  if (map == NULL) {
     return;
   }
 
-  Entry **old_entries = map->entries;
-  size_t old_buckets = map->buckets;
+  size_t capacity = map->len > min_capacity ? map->len : min_capacity;
+  size_t buckets = hashmap_buckets_for(map, capacity);
+  if (buckets < map->buckets) {
+    (void)hashmap_resize(map, buckets);
+  }
+}
 
-  map->len = 0;
-  map->buckets = new_buckets;
-  map->entries = calloc(new_buckets, sizeof(*(map->entries)));
+// Sets the value for the given key. If the key already exists, the old value is
+// returned. Takes ownership of the value and frees it when the map is freed.
+// The key is copied and no ownership is taken. If the new entry cannot be
+// allocated, NULL is returned as well and the value is not stored, so callers
+// that need to tell the two apart should use hashmap_try_insert.
+void *hashmap_insert(HashMap *map, const char *key, void *value) {
+  if (key == NULL) {
+    return NULL;
+  }
 
-  for (size_t i = 0; i < old_buckets; i++) {
-    Entry *entry = old_entries[i];
-    while (entry != NULL) {
-      hashmap_insert(map, entry->key, entry->value);
+  return hashmap_insert_bytes(map, key, strlen(key), value);
+}
 
-      Entry *next = entry->next;
-      free(entry->key);
-      free(entry);
-      entry = next;
+// Sets the value for the given key of key_len bytes, which may contain nul
+// bytes. Otherwise behaves like hashmap_insert.
+void *hashmap_insert_bytes(HashMap *map, const void *key, size_t key_len,
+                           void *value) {
+  void *old_value = NULL;
+  (void)hashmap_try_insert(map, key, key_len, value, &old_value);
+  return old_value;
+}
+
+// Sets the value for the given key of key_len bytes like hashmap_insert_bytes,
+// but reports whether the value could be stored. The old value is stored in
+// old_value if the key already exists and NULL otherwise. Returns
+// HASHMAP_ALLOC_FAILED if the buckets or the new entry could not be allocated,
+// or if map, key, or old_value is NULL, in which case the HashMap is unchanged
+// and the caller keeps ownership of the value.
+HashMapStatus hashmap_try_insert(HashMap *map, const void *key, size_t key_len,
+                                 void *value, void **old_value) {
+  if (map == NULL || key == NULL || old_value == NULL) {
+    return HASHMAP_ALLOC_FAILED;
+  }
+
+  *old_value = NULL;
+  hashmap_grow_if_needed(map);
+  uint64_t hash = 0;
+  hashmap_hash_key(map, key, key_len, &hash);
+
+  if (map->open_addressing) {
+    Entry *entry = hashmap_find(map, key, key_len, hash);
+    if (entry != NULL) {
+      *old_value = entry->value;
+      entry->value = value;
+      return HASHMAP_OK;
     }
+
+    return hashmap_slot_insert(map, key, key_len, hash, value) == NULL
+               ? HASHMAP_ALLOC_FAILED
+               : HASHMAP_OK;
+  }
+  if (map->entries == NULL) {
+    return HASHMAP_ALLOC_FAILED;
   }
 
-  free(old_entries);
-}
+  size_t bucket = hash % map->buckets;
+  Entry **entry = map->entries + bucket;
+  while (*entry != NULL) {
+    if (hashmap_key_eq(map, *entry, key, key_len)) {
+      *old_value = (*entry)->value;
+      (*entry)->value = value;
+      return HASHMAP_OK;
+    }
 
-// If the HashMap has no buckets, sets the number of buckets to 8. If the load
-// factor is greater than 0.75, doubles the number of buckets.
-static void hashmap_grow_if_needed(HashMap *map) {
-  if (map == NULL) {
-    return;
+    entry = &(*entry)->next;
   }
 
-  if (map->buckets == 0) {
-    hashmap_grow(map, 8);
-  } else if (map->len * 4 >= map->buckets * 3) {
-    // if the load factor is greater than 0.75, double the number of buckets
-    hashmap_grow(map, map->buckets * 2);
+  Entry *new_entry = hashmap_entry_new(map, key, key_len);
+  if (new_entry == NULL) {
+    return HASHMAP_ALLOC_FAILED;
+  }
+  new_entry->value = value;
+  new_entry->next = NULL;
+  *entry = new_entry;
+  hashmap_order_append(map, new_entry);
+  map->len++;
+
+  return HASHMAP_OK;
+}
+
+// Inserts the value for the given key of key_len bytes only if the key does not
+// exist yet, looking it up a single time. Returns true if the value was
+// inserted, in which case the HashMap takes ownership of it. Otherwise, either
+// because the key exists or the entry could not be allocated, the existing
+// value is left untouched and the caller keeps ownership of the value.
+bool hashmap_insert_if_absent(HashMap *map, const void *key, size_t key_len,
+                              void *value) {
+  uint64_t hash = 0;
+  if (map == NULL || key == NULL ||
+      hashmap_get_slot(map, key, key_len, &hash) != NULL) {
+    return false;
   }
+
+  return hashmap_insert_hashed(map, key, key_len, hash, value) != NULL;
 }
 
-// Creates a new HashMap with 0 buckets. Will be resized to 8 buckets when the
-// first entry is added.
-HashMap *hashmap_new() {
-  HashMap *map = calloc(1, sizeof(*map));
+// Returns the value for the given key. If the key does not exist, NULL is
+// returned.
+void *hashmap_get(const HashMap *map, const char *key) {
+  if (key == NULL) {
+    return NULL;
+  }
 
+  return hashmap_get_bytes(map, key, strlen(key));
+}
+
+// Returns the value for the given key of key_len bytes, which may contain nul
+// bytes. Otherwise behaves like hashmap_get.
+void *hashmap_get_bytes(const HashMap *map, const void *key, size_t key_len) {
   if (map == NULL) {
     return NULL;
   }
 
-  map->len = 0;
-  map->buckets = 0;
-  map->entries = NULL;
+  uint64_t hash = 0;
+  hashmap_hash_key(map, key, key_len, &hash);
+
+  Entry *entry = hashmap_find(map, key, key_len, hash);
+  return entry == NULL ? NULL : entry->value;
+}
 
-  uint8_t key[16] = {0};
-  for (size_t i = 0; i < 16; i++) {
-    key[i] = rand() % (UINT8_MAX + 1);
+// Returns the entry for the given key of key_len bytes, or NULL if the key does
+// not exist. The entry is still owned by the HashMap and must not be modified.
+const Entry *hashmap_get_entry(const HashMap *map, const void *key,
+                               size_t key_len) {
+  if (map == NULL || key == NULL) {
+    return NULL;
   }
-  memcpy(map->key, key, 16);
 
-  return map;
+  uint64_t hash = 0;
+  hashmap_hash_key(map, key, key_len, &hash);
+
+  return hashmap_find(map, key, key_len, hash);
 }
 
-// Creates a new HashMap with the given number of buckets.
-HashMap *hashmap_with_buckets(size_t buckets) {
-  HashMap *map = hashmap_new();
+// Returns the first entry found by walking the buckets of the HashMap in order,
+// or NULL if it is empty. Which entry that is depends on the hashes of the keys,
+// so it is effectively arbitrary, except for ordered HashMaps, which return the
+// oldest entry. The entry is still owned by the HashMap and must not be
+// modified.
+const Entry *hashmap_any_entry(const HashMap *map) {
+  if (map == NULL || map->len == 0) {
+    return NULL;
+  }
+  if (map->ordered) {
+    return map->order_head;
+  }
+  if (map->open_addressing) {
+    for (size_t i = 0; i < map->buckets; i++) {
+      if (map->slots[i].key != NULL) {
+        return &map->slots[i];
+      }
+    }
 
-  if (map == NULL) {
     return NULL;
   }
 
-  map->buckets = buckets;
-  map->entries = calloc(buckets, sizeof(*(map->entries)));
-  for (size_t i = 0; i < buckets; i++) {
-    map->entries[i] = NULL;
+  for (size_t i = 0; i < map->buckets; i++) {
+    if (map->entries[i] != NULL) {
+      return map->entries[i];
+    }
   }
 
-  return map;
+  return NULL;
 }
 
-// Returns the number of keys stored in the HashMap.
-size_t hashmap_len(const HashMap *map) {
-  if (map == NULL) {
-    return 0;
+// Returns true if the given key of key_len bytes exists in the HashMap. Unlike
+// hashmap_get, the result does not depend on the value, so keys whose value is
+// NULL are still reported as present.
+bool hashmap_contains(const HashMap *map, const void *key, size_t key_len) {
+  return hashmap_get_entry(map, key, key_len) != NULL;
+}
+
+// Returns the hash of the given key of key_len bytes as computed by the HashMap,
+// using its custom hasher if it has one and SipHash with its secret key
+// otherwise.
+uint64_t hashmap_hash(const HashMap *map, const void *key, size_t key_len) {
+  uint64_t hash = 0;
+  hashmap_hash_key(map, key, key_len, &hash);
+  return hash;
+}
+
+// Returns the index of the bucket the given key of key_len bytes hashes to
+// with the current number of buckets, whether or not the key is present, or
+// SIZE_MAX if the HashMap has no buckets yet.
+size_t hashmap_bucket_index(const HashMap *map, const void *key,
+                            size_t key_len) {
+  if (map == NULL || map->buckets == 0) {
+    return SIZE_MAX;
   }
 
-  return map->len;
+  return hashmap_hash(map, key, key_len) % map->buckets;
 }
 
-// Returns the ratio of keys to buckets in the HashMap.
-double hashmap_load_factor(const HashMap *map) {
-  if (map == NULL) {
-    return 0;
+// Returns a pointer to the value slot for the given key of key_len bytes, or
+// NULL if the key does not exist. The hash of the key is stored in hash so that
+// a following hashmap_insert_hashed does not need to compute it again.
+void **hashmap_get_slot(const HashMap *map, const void *key, size_t key_len,
+                        uint64_t *hash) {
+  if (map == NULL || key == NULL || hash == NULL) {
+    return NULL;
   }
 
-  return (double)map->len / map->buckets;
+  hashmap_hash_key(map, key, key_len, hash);
+  return hashmap_get_slot_hashed(map, key, key_len, *hash);
 }
 
-// Sets the value for the given key. If the key already exists, the old value is
-// returned. Takes ownership of the value and frees it when the map is freed.
-// The key is copied and no ownership is taken.
-void *hashmap_insert(HashMap *map, const char *key, void *value) {
+// Returns a pointer to the value slot for the given key of key_len bytes using
+// a previously computed hash, or NULL if the key does not exist. The hash must
+// be the one hashmap_hash returns for the key, otherwise the key is looked for
+// in the wrong bucket.
+void **hashmap_get_slot_hashed(const HashMap *map, const void *key,
+                               size_t key_len, uint64_t hash) {
+  if (map == NULL || key == NULL) {
+    return NULL;
+  }
+
+  Entry *entry = hashmap_find(map, key, key_len, hash);
+  return entry == NULL ? NULL : &entry->value;
+}
+
+// Inserts a new entry for the key of key_len bytes using a hash previously
+// computed by hashmap_get_slot and returns a pointer to its value slot, or NULL
+// if the entry could not be allocated. The key must not already exist in the
+// HashMap. Takes ownership of the value and copies the key like hashmap_insert.
+void **hashmap_insert_hashed(HashMap *map, const void *key, size_t key_len,
+                             uint64_t hash, void *value) {
   hashmap_grow_if_needed(map);
-  if (map->entries == NULL) {
+  if (map != NULL && map->open_addressing) {
+    Entry *entry = hashmap_slot_insert(map, key, key_len, hash, value);
+    return entry == NULL ? NULL : &entry->value;
+  }
+  if (map == NULL || map->entries == NULL) {
     return NULL;
   }
 
-  uint64_t hash = 0;
-  hashmap_hash_key(map, key, &hash);
+  Entry *entry = hashmap_entry_new(map, key, key_len);
+  if (entry == NULL) {
+    return NULL;
+  }
 
+  // the key is known to be absent, so the entry can go at the head of the chain
   size_t bucket = hash % map->buckets;
-  Entry **entry = map->entries + bucket;
-  while (*entry != NULL) {
-    if (strcmp((*entry)->key, key) == 0) {
-      void *old_value = (*entry)->value;
-      (*entry)->value = value;
-      return old_value;
-    }
+  entry->value = value;
+  entry->next = map->entries[bucket];
+  map->entries[bucket] = entry;
+  hashmap_order_append(map, entry);
+  map->len++;
 
-    entry = &(*entry)->next;
+  return &entry->value;
+}
+
+// Deletes the entry for the given key and returns the value, handing ownership
+// of the value to the caller. Returns NULL if the key does not exist. Removing
+// only unlinks the entry and never resizes the HashMap, so the buckets stay
+// allocated until hashmap_shrink is called.
+void *hashmap_remove(HashMap *map, const char *key) {
+  if (key == NULL) {
+    return NULL;
   }
 
-  *entry = calloc(1, sizeof(**entry));
-  (*entry)->key = strdup(key);
-  (*entry)->value = value;
-  (*entry)->next = NULL;
-  map->len++;
+  return hashmap_remove_bytes(map, key, strlen(key));
+}
 
-  return NULL;
+// Deletes the entry for the given key of key_len bytes, which may contain nul
+// bytes. Otherwise behaves like hashmap_remove.
+void *hashmap_remove_bytes(HashMap *map, const void *key, size_t key_len) {
+  return hashmap_remove_entry(map, key, key_len, NULL, NULL);
 }
 
-// Returns the value for the given key. If the key does not exist, NULL is
-// returned.
-void *hashmap_get(const HashMap *map, const char *key) {
-  if (map == NULL || map->entries == NULL) {
-    printf("map or entries is null\n");
+// Deletes the entry for the given key of key_len bytes like
+// hashmap_remove_bytes, but also hands ownership of the stored key to the
+// caller through stored_key and its length through stored_key_len. The stored
+// key is followed by a nul byte and must be freed with hashmap_key_free. If
+// stored_key is NULL, the stored key is freed instead. Both are left untouched
+// if the key does not exist. A HashMap using an arena, or storing the key
+// inline, hands out a copy of the stored key instead and returns NULL without
+// removing the entry if the copy cannot be allocated.
+void *hashmap_remove_entry(HashMap *map, const void *key, size_t key_len,
+                           char **stored_key, size_t *stored_key_len) {
+  if (map == NULL || map->buckets == 0) {
     return NULL;
   }
 
   uint64_t hash = 0;
-  hashmap_hash_key(map, key, &hash);
+  hashmap_hash_key(map, key, key_len, &hash);
+
+  if (map->open_addressing) {
+    Entry *entry = hashmap_find(map, key, key_len, hash);
+    if (entry == NULL) {
+      return NULL;
+    }
+
+    void *value = entry->value;
+    if (stored_key != NULL) {
+      char *removed_key = entry->key;
+      if (map->arena != NULL) {
+        removed_key = hashmap_key_dup(NULL, removed_key, entry->key_len);
+        if (removed_key == NULL) {
+          return NULL;
+        }
+      }
+      *stored_key = removed_key;
+      if (stored_key_len != NULL) {
+        *stored_key_len = entry->key_len;
+      }
+    } else {
+      hashmap_node_free(map->arena, entry->key);
+    }
+    hashmap_slot_remove(map, (size_t)(entry - map->slots));
+    map->len--;
+    return value;
+  }
 
   size_t bucket = hash % map->buckets;
-  Entry *entry = map->entries[bucket];
-  while (entry != NULL) {
-    if (strcmp(entry->key, key) == 0) {
-      return entry->value;
+  Entry **entry = map->entries + bucket;
+  while (*entry != NULL) {
+    if (hashmap_key_eq(map, *entry, key, key_len)) {
+      void *value = (*entry)->value;
+      Entry *next = (*entry)->next;
+      bool copy_key = map->arena != NULL || hashmap_key_is_inline(map, *entry);
+      if (stored_key != NULL) {
+        // keys inline or in an arena can't be freed on their own, so the
+        // caller gets a copy from the heap
+        char *removed_key = (*entry)->key;
+        if (copy_key) {
+          removed_key = hashmap_key_dup(NULL, removed_key, (*entry)->key_len);
+          if (removed_key == NULL) {
+            return NULL;
+          }
+        }
+        *stored_key = removed_key;
+        if (stored_key_len != NULL) {
+          *stored_key_len = (*entry)->key_len;
+        }
+      }
+      hashmap_order_unlink(map, *entry);
+      hashmap_entry_free(map, *entry, stored_key == NULL || copy_key);
+      *entry = next;
+      map->len--;
+      return value;
     }
 
-    entry = entry->next;
+    entry = &(*entry)->next;
   }
 
   return NULL;
 }
 
-// Deletes the entry for the given key and returns the value, handing ownership
-// of the value to the caller. Returns NULL if the key does not exist.
-void *hashmap_remove(HashMap *map, const char *key) {
-  if (map == NULL || map->entries == NULL) {
+// Deletes the entry whose value is stored at the given slot and returns the
+// value, handing ownership of it to the caller. The slot and hash must come
+// from hashmap_get_slot or hashmap_insert_hashed with no modification of the
+// HashMap since. The entry is found by the address of its slot rather than by
+// comparing keys, so removing a key that was just looked up does not compare
+// it again. Returns NULL if no entry has the slot.
+void *hashmap_remove_slot(HashMap *map, void **slot, uint64_t hash) {
+  if (map == NULL || slot == NULL || map->buckets == 0) {
     return NULL;
   }
 
-  uint64_t hash = 0;
-  hashmap_hash_key(map, key, &hash);
+  if (map->open_addressing) {
+    Entry *entry = (Entry *)((char *)slot - offsetof(Entry, value));
+    void *value = entry->value;
+    hashmap_node_free(map->arena, entry->key);
+    hashmap_slot_remove(map, (size_t)(entry - map->slots));
+    map->len--;
+    return value;
+  }
 
-  size_t bucket = hash % map->buckets;
-  Entry **entry = map->entries + bucket;
+  Entry **entry = map->entries + hash % map->buckets;
   while (*entry != NULL) {
-    if (strcmp((*entry)->key, key) == 0) {
+    if (&(*entry)->value == slot) {
       void *value = (*entry)->value;
       Entry *next = (*entry)->next;
-      free((*entry)->key);
-      free(*entry);
+      hashmap_order_unlink(map, *entry);
+      hashmap_entry_free(map, *entry, true);
       *entry = next;
+      map->len--;
       return value;
     }
 
@@ -204,27 +1596,294 @@ void *hashmap_remove(HashMap *map, const char *key) {
   return NULL;
 }
 
-// Frees the HashMap and all its entries, setting the pointer to map to NULL.
-// Will also free all keys and values.
-void hashmap_free(HashMap **map) {
-  if (map == NULL || *map == NULL) {
+// Frees a key handed out by hashmap_remove_entry.
+void hashmap_key_free(char *key) {
+  HASHMAP_FREE(key);
+}
+
+// Removes every entry from the HashMap while keeping its buckets allocated.
+// Keys are freed and free_value is called on each value unless it is NULL, in
+// which case ownership of the values must have been taken by the caller. An
+// ordered HashMap calls free_value in insertion order, so that values are freed
+// in the same order every time. An arena is emptied but keeps its largest block
+// for the entries to come.
+void hashmap_clear(HashMap *map, void (*free_value)(void *value)) {
+  if (map == NULL) {
     return;
   }
 
-  for (size_t i = 0; i < (*map)->buckets; i++) {
-    Entry *entry = (*map)->entries[i];
+  if (map->ordered && free_value != NULL) {
+    for (Entry *entry = map->order_head; entry != NULL;
+         entry = entry->order_next) {
+      if (entry->value != NULL) {
+        free_value(entry->value);
+      }
+    }
+    // the values are gone, only the entries are left to free
+    free_value = NULL;
+  }
+
+  for (size_t i = 0; i < map->buckets; i++) {
+    if (map->open_addressing) {
+      if (map->slots[i].key == NULL) {
+        continue;
+      }
+      if (free_value != NULL && map->slots[i].value != NULL) {
+        free_value(map->slots[i].value);
+      }
+      hashmap_node_free(map->arena, map->slots[i].key);
+      memset(&map->slots[i], 0, sizeof(map->slots[i]));
+      continue;
+    }
+
+    Entry *entry = map->entries[i];
     while (entry != NULL) {
       Entry *next = entry->next;
-      free(entry->key);
-      if (entry->value != NULL) {
-        free(entry->value);
+      if (free_value != NULL && entry->value != NULL) {
+        free_value(entry->value);
       }
-      free(entry);
+      hashmap_entry_free(map, entry, true);
       entry = next;
     }
+    map->entries[i] = NULL;
+  }
+
+  if (map->arena != NULL) {
+    hashmap_arena_reset(map->arena);
+  }
+  map->order_head = NULL;
+  map->order_tail = NULL;
+  map->len = 0;
+}
+
+// Frees the HashMap and all its entries, setting the pointer to map to NULL.
+// Will also free all keys and values.
+void hashmap_free(HashMap **map) {
+  hashmap_free_with(map, HASHMAP_FREE);
+}
+
+// Frees the HashMap and all its entries like hashmap_free, but calls free_value
+// on each value instead of free. If free_value is NULL, values are not freed
+// and ownership of them must have been taken by the caller.
+void hashmap_free_with(HashMap **map, void (*free_value)(void *value)) {
+  if (map == NULL || *map == NULL) {
+    return;
   }
 
-  free((*map)->entries);
-  free(*map);
+  hashmap_clear(*map, free_value);
+  hashmap_arena_free((*map)->arena);
+  HASHMAP_FREE((*map)->entries);
+  HASHMAP_FREE((*map)->slots);
+  HASHMAP_FREE(*map);
   *map = NULL;
 }
+
+// Creates a new iterator over the entries of the HashMap. The HashMap must not
+// be modified while the iterator is in use, except through
+// hashmap_iter_remove.
+HashMapIter *hashmap_iter_new(const HashMap *map) {
+  HashMapIter *iter = HASHMAP_CALLOC(1, sizeof(*iter));
+
+  if (iter == NULL) {
+    return NULL;
+  }
+
+  iter->map = map;
+  iter->bucket = 0;
+  iter->next = NULL;
+  iter->current = NULL;
+  iter->order_next = map != NULL && map->ordered ? map->order_head : NULL;
+  iter->order_current = NULL;
+  iter->slot_start = 0;
+  iter->slot_current = false;
+  if (map != NULL && map->open_addressing) {
+    // starting at an empty slot means no run of probed slots wraps around the
+    // start, so shifting entries back on removal never moves a visited entry
+    // past the ones still to come
+    while (iter->slot_start < map->buckets &&
+           map->slots[iter->slot_start].key != NULL) {
+      iter->slot_start++;
+    }
+  }
+
+  return iter;
+}
+
+// Advances the iterator, storing the key, its length, and the value of the next
+// entry in key, key_len, and value. Returns false once every entry has been
+// visited.
+bool hashmap_iter_next(HashMapIter *iter, const char **key, size_t *key_len,
+                       void **value) {
+  if (iter == NULL || iter->map == NULL) {
+    return false;
+  }
+
+  Entry *entry = NULL;
+  iter->current = NULL;
+  iter->slot_current = false;
+  if (iter->map->open_addressing) {
+    const HashMap *map = iter->map;
+    do {
+      if (iter->bucket >= map->buckets) {
+        return false;
+      }
+
+      entry = &map->slots[(iter->slot_start + iter->bucket++) % map->buckets];
+    } while (entry->key == NULL);
+    iter->slot_current = true;
+  } else if (iter->map->ordered) {
+    entry = iter->order_next;
+    iter->order_current = entry;
+    if (entry == NULL) {
+      return false;
+    }
+    iter->order_next = entry->order_next;
+  } else {
+    while (iter->next == NULL || *iter->next == NULL) {
+      if (iter->bucket >= iter->map->buckets) {
+        return false;
+      }
+
+      iter->next = &iter->map->entries[iter->bucket++];
+    }
+
+    entry = *iter->next;
+    iter->current = iter->next;
+    iter->next = &entry->next;
+  }
+
+  if (key != NULL) {
+    *key = entry->key;
+  }
+  if (key_len != NULL) {
+    *key_len = entry->key_len;
+  }
+  if (value != NULL) {
+    *value = entry->value;
+  }
+
+  return true;
+}
+
+// Deletes the entry last visited by the iterator from the HashMap it iterates
+// over and returns the value, handing ownership of the value to the caller.
+// Returns NULL if no entry has been visited since the last call to
+// hashmap_iter_next or if map is not the HashMap being iterated over.
+void *hashmap_iter_remove(HashMap *map, HashMapIter *iter) {
+  if (map == NULL || iter == NULL || iter->map != map) {
+    return NULL;
+  }
+
+  Entry *entry = NULL;
+  if (map->open_addressing) {
+    if (!iter->slot_current) {
+      return NULL;
+    }
+
+    // the slot is visited again, since the entry probed after it may have
+    // been shifted back into it
+    iter->slot_current = false;
+    iter->bucket--;
+    size_t i = (iter->slot_start + iter->bucket) % map->buckets;
+    void *value = map->slots[i].value;
+    hashmap_node_free(map->arena, map->slots[i].key);
+    hashmap_slot_remove(map, i);
+    map->len--;
+    return value;
+  }
+  if (map->ordered) {
+    if (iter->order_current == NULL) {
+      return NULL;
+    }
+
+    // the walk continues from order_next, which removing the entry leaves
+    // alone, but the link to the entry in its chain has to be looked up
+    entry = iter->order_current;
+    iter->order_current = NULL;
+    uint64_t hash = 0;
+    hashmap_hash_key(map, entry->key, entry->key_len, &hash);
+    Entry **link = map->entries + hash % map->buckets;
+    while (*link != entry) {
+      link = &(*link)->next;
+    }
+    *link = entry->next;
+    hashmap_order_unlink(map, entry);
+  } else {
+    if (iter->current == NULL) {
+      return NULL;
+    }
+
+    // unlink the entry so the link that pointed to it now points to the entry
+    // after it, which is also where the walk continues from
+    entry = *iter->current;
+    *iter->current = entry->next;
+    iter->next = iter->current;
+    iter->current = NULL;
+  }
+
+  void *value = entry->value;
+  hashmap_entry_free(map, entry, true);
+  map->len--;
+
+  return value;
+}
+
+// Frees the iterator, setting the pointer to iter to NULL. The HashMap it was
+// iterating over is left untouched.
+void hashmap_iter_free(HashMapIter **iter) {
+  if (iter == NULL || *iter == NULL) {
+    return;
+  }
+
+  HASHMAP_FREE(*iter);
+  *iter = NULL;
+}
+
+// Stores the key, its length, and the value of the entry at the given position
+// in key, key_len, and value, and moves the position past it. The buckets are
+// walked in order even if the HashMap is ordered. Returns false once there are
+// no entries left after the position.
+bool hashmap_position_next(const HashMap *map, HashMapPosition *position,
+                           const char **key, size_t *key_len, void **value) {
+  if (map == NULL || position == NULL || map->buckets == 0) {
+    return false;
+  }
+  if (map->open_addressing) {
+    // every slot holds at most one entry, at offset 0
+    while (position->bucket < map->buckets) {
+      Entry *slot = &map->slots[position->bucket];
+      if (position->offset == 0 && slot->key != NULL) {
+        *key = slot->key;
+        *key_len = slot->key_len;
+        *value = slot->value;
+        position->offset = 1;
+        return true;
+      }
+
+      position->bucket++;
+      position->offset = 0;
+    }
+
+    return false;
+  }
+
+  while (position->bucket < map->buckets) {
+    Entry *entry = map->entries[position->bucket];
+    for (size_t i = 0; i < position->offset && entry != NULL; i++) {
+      entry = entry->next;
+    }
+
+    if (entry != NULL) {
+      *key = entry->key;
+      *key_len = entry->key_len;
+      *value = entry->value;
+      position->offset++;
+      return true;
+    }
+
+    position->bucket++;
+    position->offset = 0;
+  }
+
+  return false;
+}
@@ -1,52 +1,433 @@
 #include "siphash/siphash.h"
 
+#include <stdbool.h>
 #include <stdlib.h>
 #include <string.h>
 
 #pragma once
 
-// Entry in the HashMap that acts as a node in a linked list.
+// Entry in the HashMap that acts as a node in a linked list. The key is stored
+// with its length so it may contain nul bytes, but it is always followed by a
+// nul byte as well. In an ordered HashMap, order_prev and order_next also
+// thread every entry into a doubly linked list in insertion order, at the cost
+// of two extra pointers per entry, which are NULL otherwise.
 typedef struct Entry {
   char *key;
+  size_t key_len;
   void *value;
   struct Entry *next;
+  struct Entry *order_prev;
+  struct Entry *order_next;
 } Entry;
 
-// HashMap that uses SipHash-2-4 to hash keys. Collisions are resolved by
-// chaining in a linked list.
+// Status returned by operations that may need to allocate.
+typedef enum HashMapStatus {
+  HASHMAP_OK,
+  HASHMAP_CAPACITY_OVERFLOW,
+  HASHMAP_ALLOC_FAILED,
+} HashMapStatus;
+
+// Function used to hash a key of key_len bytes in place of SipHash.
+typedef uint64_t (*HashMapHasher)(const char *key, size_t key_len);
+
+// Function called with the data it was registered with after a HashMap resized
+// from old_buckets to new_buckets.
+typedef void (*HashMapResizeCallback)(void *data, size_t old_buckets,
+                                      size_t new_buckets);
+
+// Bump allocator a HashMap can take its Entry nodes and keys from. Its memory
+// is only ever released all at once.
+typedef struct HashMapArena HashMapArena;
+
+// HashMap that uses SipHash-2-4 to hash keys unless a custom hasher is set.
+// Collisions are resolved by chaining in a linked list. The number of buckets is
+// multiplied by growth_factor, which defaults to 2, whenever the load factor
+// would exceed max_load_factor, which defaults to 0.75. If prime_buckets is set,
+// the number of buckets is always a prime number instead of a power of two. If
+// case_insensitive is set, keys that differ only in ASCII case are equal. If
+// on_resize is set, it is called with on_resize_data after every resize. If
+// arena is set, entries and their keys are allocated from it. If inline_keys
+// is set, short keys share the allocation of their entry. If ordered is
+// set, entries are iterated from order_head to order_tail in insertion order.
+// If open_addressing is set, the HashMap keeps its entries in the slots array
+// instead of entries, one per slot, and resolves collisions by linear probing.
+// If the library is built with HASHMAP_PROBE_STATS defined, probes counts
+// every entry compared against a key while looking it up. Lookups take a const
+// HashMap and may run on several threads at once, yet still bump probes, so it
+// must only ever be updated atomically while the HashMap is shared.
 typedef struct HashMap {
   size_t len;
   size_t buckets;
   uint8_t key[16];
+  HashMapHasher hasher;
+  double max_load_factor;
+  double growth_factor;
+  bool prime_buckets;
+  bool case_insensitive;
+  HashMapResizeCallback on_resize;
+  void *on_resize_data;
+  HashMapArena *arena;
+  bool inline_keys;
+  bool ordered;
+  Entry *order_head;
+  Entry *order_tail;
+  bool open_addressing;
+  Entry *slots;
+#ifdef HASHMAP_PROBE_STATS
+  uint64_t probes;
+#endif
   Entry **entries;
 } HashMap;
 
+// Iterator over the entries of a HashMap. Entries are visited bucket by bucket,
+// so the order is unrelated to the order of insertion. The iterator keeps track
+// of the links pointing to the next and the last visited entry so that the
+// last visited entry can be removed without disturbing the walk. Ordered
+// HashMaps are walked in insertion order through order_next and order_current
+// instead. Open addressing HashMaps are walked slot by slot starting from the
+// empty slot slot_start, with bucket counting the slots passed so far and
+// slot_current telling whether the last of them can still be removed.
+typedef struct HashMapIter {
+  const HashMap *map;
+  size_t bucket;
+  Entry **next;
+  Entry **current;
+  Entry *order_next;
+  Entry *order_current;
+  size_t slot_start;
+  bool slot_current;
+} HashMapIter;
+
+// Position in the walk over the buckets of a HashMap that can be saved and
+// resumed from later, unlike a HashMapIter: the next entry is the one offset
+// entries into the chain of the given bucket, or the first one after it.
+// Resuming after the HashMap was modified is safe, but may skip or repeat
+// entries.
+typedef struct HashMapPosition {
+  size_t bucket;
+  size_t offset;
+} HashMapPosition;
+
+// Statistics about how the entries of a HashMap are distributed across its
+// buckets. The mean chain length only counts buckets that are not empty. For an
+// open addressing HashMap, the buckets are its slots and the chain of a key is
+// the run of slots probed to find it, so the mean is taken over every key.
+typedef struct HashMapStats {
+  size_t buckets;
+  size_t empty_buckets;
+  size_t max_chain_len;
+  double mean_chain_len;
+} HashMapStats;
+
 // Creates a new HashMap with 0 buckets. Will be resized to 8 buckets when the
-// first entry is added.
-HashMap *hashmap_new();
+// first entry is added. Keys are hashed with the given 16 byte secret key,
+// which should be random to protect against collision attacks. If key is NULL,
+// an all-zero key is used.
+HashMap *hashmap_new(const uint8_t key[16]);
+
+// Creates a new HashMap with the given number of buckets. The secret key is
+// handled as in hashmap_new. Keys are placed by taking their hash modulo the
+// number of buckets, so any number works, and 0 buckets allocates nothing,
+// giving the same HashMap as hashmap_new. Returns NULL if the buckets cannot be
+// allocated, including when the size of their array would not fit in a size_t.
+HashMap *hashmap_with_buckets(size_t buckets, const uint8_t key[16]);
+
+// Creates a new HashMap whose number of buckets is always a prime number from
+// hashmap_primes, starting with the smallest one that is at least min_buckets.
+// Prime numbers of buckets spread keys more evenly when the hash function is
+// weak. The secret key is handled as in hashmap_new. Returns NULL like
+// hashmap_with_buckets, or if no prime in the table is large enough.
+HashMap *hashmap_with_prime_buckets(size_t min_buckets, const uint8_t key[16]);
+
+// Sets the function used to hash keys instead of SipHash, or restores SipHash
+// if hasher is NULL. Since existing keys would not be rehashed, the hasher can
+// only be changed while the HashMap is empty. Returns true if it was changed.
+bool hashmap_set_hasher(HashMap *map, HashMapHasher hasher);
+
+// Makes the HashMap hash and compare keys with their ASCII letters lowercased,
+// so that keys differing only in ASCII case are treated as the same key, or
+// restores exact comparison if case_insensitive is false. Other bytes are
+// compared as they are. Stored keys keep the case they were first inserted
+// with. A custom hasher receives keys unchanged and must itself ignore ASCII
+// case. Like the hasher, this can only be changed while the HashMap is empty.
+// Returns true if it was changed.
+bool hashmap_set_case_insensitive(HashMap *map, bool case_insensitive);
+
+// Makes the HashMap allocate its entries and their keys from an arena instead
+// of one by one. Removing an entry no longer frees its memory, which is only
+// reclaimed once the HashMap is cleared or freed, so this suits HashMaps that
+// are filled and then freed as a whole. Can only be enabled while the HashMap
+// is empty and never disabled again. Returns true if the HashMap uses an arena
+// afterwards.
+bool hashmap_use_arena(HashMap *map);
 
-// Creates a new HashMap with the given number of buckets.
-HashMap *hashmap_with_buckets(size_t buckets);
+// Makes the HashMap store keys of at most 15 bytes inline, in the same
+// allocation right after their entry, instead of in an allocation of their own.
+// Comparing short keys then reads memory next to the entry rather than chasing
+// a pointer elsewhere on the heap. Longer keys are stored as usual. Can only be
+// changed while the HashMap is empty and not enabled for open addressing
+// HashMaps. Returns true if it was changed.
+bool hashmap_set_inline_keys(HashMap *map, bool inline_keys);
+
+// Makes the HashMap remember the order its keys were inserted in, so that
+// iterating over it and hashmap_any_entry follow that order. Replacing the
+// value of an existing key keeps its position, while removing a key and
+// inserting it again moves it to the end. Every entry carries the two order
+// pointers either way, so this only costs the time to maintain them. Can only
+// be changed while the HashMap is empty and not enabled for open addressing
+// HashMaps. Returns true if it was changed.
+bool hashmap_set_ordered(HashMap *map, bool ordered);
+
+// Makes the HashMap store its entries directly in an array of slots and
+// resolve collisions by linear probing instead of chaining, so that looking up
+// a key walks neighbouring slots rather than following pointers. Removing an
+// entry shifts the entries probed past it back instead of leaving a tombstone.
+// Can only be enabled while the HashMap is empty, neither ordered nor storing
+// keys inline, and never disabled again. Returns true if the HashMap uses open
+// addressing afterwards.
+bool hashmap_use_open_addressing(HashMap *map);
+
+// Sets the function called with data and the old and new number of buckets
+// whenever the HashMap has finished resizing, whether it grew on insertion,
+// was reserved, or shrank. Passing NULL as callback removes it, which is the
+// default.
+void hashmap_set_resize_callback(HashMap *map, HashMapResizeCallback callback,
+                                 void *data);
+
+// Sets the load factor the HashMap may reach before it grows. The factor must
+// be at least 0.1 and less than 1. If the HashMap already exceeds the new
+// maximum, it grows right away. Returns true if the factor was changed.
+bool hashmap_set_max_load_factor(HashMap *map, double factor);
+
+// Sets the factor the number of buckets is multiplied by whenever the HashMap
+// grows on insertion. The factor must be greater than 1 and finite. Does not
+// affect hashmap_reserve or hashmap_reserve_exact, which grow straight to the
+// number of buckets they need. Returns true if the factor was changed.
+bool hashmap_set_growth_factor(HashMap *map, double factor);
 
 // Returns the number of keys stored in the HashMap.
 size_t hashmap_len(const HashMap *map);
 
-// Returns the ratio of keys to buckets in the HashMap.
+// Returns the number of buckets allocated by the HashMap.
+size_t hashmap_bucket_count(const HashMap *map);
+
+// Returns the number of keys the HashMap can hold before it grows.
+size_t hashmap_capacity(const HashMap *map);
+
+// Returns the ratio of keys to buckets in the HashMap, or 0 if it has no
+// buckets.
 double hashmap_load_factor(const HashMap *map);
 
+// Fills in stats by walking every bucket of the HashMap and measuring the length
+// of its chain.
+void hashmap_stats(const HashMap *map, HashMapStats *stats);
+
+// Returns an estimate of the number of bytes allocated by the HashMap itself:
+// the HashMap struct, its bucket array, and for every entry the Entry node and
+// the copy of its key including the trailing nul byte. Values are not counted
+// since they are owned by the caller, and neither is the bookkeeping overhead
+// of the allocator. Walks every entry to add up the key lengths. For a HashMap
+// using an arena, the blocks of the arena are counted in place of the entries.
+// An open addressing HashMap counts its slots in place of the bucket array and
+// the Entry nodes.
+size_t hashmap_memory_usage(const HashMap *map);
+
+// Checks the internal invariants of the HashMap by walking every entry: that
+// the length matches the number of entries, that every entry sits where a
+// lookup of its key would find it, that no chain is cyclic, that an open
+// addressing HashMap keeps an empty slot, and that the order list of an
+// ordered HashMap links every entry back and forth. Returns NULL if they hold,
+// or otherwise a static string describing the first one found broken. The
+// load factor can exceed the maximum after a failed resize, so only the
+// factors themselves are checked. Meant for tests and fuzzers.
+const char *hashmap_validate(const HashMap *map);
+
+// Grows the HashMap once so that additional more keys fit without the load
+// factor exceeding the maximum load factor, rehashing the existing entries a
+// single time. Never shrinks the HashMap. Returns HASHMAP_CAPACITY_OVERFLOW if
+// no number of buckets can hold that many keys and HASHMAP_ALLOC_FAILED if the
+// buckets cannot be allocated, leaving the HashMap unchanged in both cases.
+HashMapStatus hashmap_reserve(HashMap *map, size_t additional);
+
+// Grows the HashMap once to exactly the fewest buckets that fit additional
+// more keys without the load factor exceeding the maximum load factor, even if
+// that is not a power of two. HashMaps using prime numbers of buckets round up
+// to the next prime number rather than to one from their table. Later growth
+// on insertion follows the growth factor as usual. Never shrinks the HashMap
+// and returns the same statuses as hashmap_reserve.
+HashMapStatus hashmap_reserve_exact(HashMap *map, size_t additional);
+
+// Shrinks the HashMap to the smallest power of two number of buckets, but at
+// least 8, that holds its keys without the load factor exceeding the maximum
+// load factor. HashMaps using prime numbers of buckets shrink to the smallest
+// fitting prime from their table instead. Never grows the HashMap, so one
+// without buckets stays that way.
+void hashmap_shrink(HashMap *map);
+
+// Shrinks the HashMap like hashmap_shrink, but keeps enough buckets to hold at
+// least min_capacity keys without the load factor exceeding the maximum load
+// factor. If the HashMap holds more keys than that, it shrinks only as far as
+// its keys allow. Never grows the HashMap.
+void hashmap_shrink_to(HashMap *map, size_t min_capacity);
+
 // Sets the value for the given key. If the key already exists, the old value is
 // returned. Takes ownership of the value and frees it when the map is freed.
-// The key is copied and no ownership is taken.
+// The key is copied and no ownership is taken. If the new entry cannot be
+// allocated, NULL is returned as well and the value is not stored, so callers
+// that need to tell the two apart should use hashmap_try_insert.
 void *hashmap_insert(HashMap *map, const char *key, void *value);
 
+// Sets the value for the given key of key_len bytes, which may contain nul
+// bytes. Otherwise behaves like hashmap_insert.
+void *hashmap_insert_bytes(HashMap *map, const void *key, size_t key_len,
+                           void *value);
+
+// Sets the value for the given key of key_len bytes like hashmap_insert_bytes,
+// but reports whether the value could be stored. The old value is stored in
+// old_value if the key already exists and NULL otherwise. Returns
+// HASHMAP_ALLOC_FAILED if the buckets or the new entry could not be allocated,
+// or if map, key, or old_value is NULL, in which case the HashMap is unchanged
+// and the caller keeps ownership of the value.
+HashMapStatus hashmap_try_insert(HashMap *map, const void *key, size_t key_len,
+                                 void *value, void **old_value);
+
+// Inserts the value for the given key of key_len bytes only if the key does not
+// exist yet, looking it up a single time. Returns true if the value was
+// inserted, in which case the HashMap takes ownership of it. Otherwise, either
+// because the key exists or the entry could not be allocated, the existing
+// value is left untouched and the caller keeps ownership of the value.
+bool hashmap_insert_if_absent(HashMap *map, const void *key, size_t key_len,
+                              void *value);
+
 // Returns the value for the given key. If the key does not exist, NULL is
 // returned.
 void *hashmap_get(const HashMap *map, const char *key);
 
+// Returns the value for the given key of key_len bytes, which may contain nul
+// bytes. Otherwise behaves like hashmap_get.
+void *hashmap_get_bytes(const HashMap *map, const void *key, size_t key_len);
+
+// Returns the entry for the given key of key_len bytes, or NULL if the key does
+// not exist. The entry is still owned by the HashMap and must not be modified.
+const Entry *hashmap_get_entry(const HashMap *map, const void *key,
+                               size_t key_len);
+
+// Returns the first entry found by walking the buckets of the HashMap in order,
+// or NULL if it is empty. Which entry that is depends on the hashes of the keys,
+// so it is effectively arbitrary, except for ordered HashMaps, which return the
+// oldest entry. The entry is still owned by the HashMap and must not be
+// modified.
+const Entry *hashmap_any_entry(const HashMap *map);
+
+// Returns true if the given key of key_len bytes exists in the HashMap. Unlike
+// hashmap_get, the result does not depend on the value, so keys whose value is
+// NULL are still reported as present.
+bool hashmap_contains(const HashMap *map, const void *key, size_t key_len);
+
+// Returns the hash of the given key of key_len bytes as computed by the HashMap,
+// using its custom hasher if it has one and SipHash with its secret key
+// otherwise.
+uint64_t hashmap_hash(const HashMap *map, const void *key, size_t key_len);
+
+// Returns the index of the bucket the given key of key_len bytes hashes to
+// with the current number of buckets, whether or not the key is present, or
+// SIZE_MAX if the HashMap has no buckets yet.
+size_t hashmap_bucket_index(const HashMap *map, const void *key,
+                            size_t key_len);
+
+// Returns a pointer to the value slot for the given key of key_len bytes, or
+// NULL if the key does not exist. The hash of the key is stored in hash so that
+// a following hashmap_insert_hashed does not need to compute it again.
+void **hashmap_get_slot(const HashMap *map, const void *key, size_t key_len,
+                        uint64_t *hash);
+
+// Returns a pointer to the value slot for the given key of key_len bytes using
+// a previously computed hash, or NULL if the key does not exist. The hash must
+// be the one hashmap_hash returns for the key, otherwise the key is looked for
+// in the wrong bucket.
+void **hashmap_get_slot_hashed(const HashMap *map, const void *key,
+                               size_t key_len, uint64_t hash);
+
+// Inserts a new entry for the key of key_len bytes using a hash previously
+// computed by hashmap_get_slot and returns a pointer to its value slot, or NULL
+// if the entry could not be allocated. The key must not already exist in the
+// HashMap. Takes ownership of the value and copies the key like hashmap_insert.
+void **hashmap_insert_hashed(HashMap *map, const void *key, size_t key_len,
+                             uint64_t hash, void *value);
+
 // Deletes the entry for the given key and returns the value, handing ownership
-// of the value to the caller. Returns NULL if the key does not exist.
+// of the value to the caller. Returns NULL if the key does not exist. Removing
+// only unlinks the entry and never resizes the HashMap, so the buckets stay
+// allocated until hashmap_shrink is called.
 void *hashmap_remove(HashMap *map, const char *key);
 
+// Deletes the entry for the given key of key_len bytes, which may contain nul
+// bytes. Otherwise behaves like hashmap_remove.
+void *hashmap_remove_bytes(HashMap *map, const void *key, size_t key_len);
+
+// Deletes the entry for the given key of key_len bytes like
+// hashmap_remove_bytes, but also hands ownership of the stored key to the
+// caller through stored_key and its length through stored_key_len. The stored
+// key is followed by a nul byte and must be freed with hashmap_key_free. If
+// stored_key is NULL, the stored key is freed instead. Both are left untouched
+// if the key does not exist. A HashMap using an arena, or storing the key
+// inline, hands out a copy of the stored key instead and returns NULL without
+// removing the entry if the copy cannot be allocated.
+void *hashmap_remove_entry(HashMap *map, const void *key, size_t key_len,
+                           char **stored_key, size_t *stored_key_len);
+
+// Deletes the entry whose value is stored at the given slot and returns the
+// value, handing ownership of it to the caller. The slot and hash must come
+// from hashmap_get_slot or hashmap_insert_hashed with no modification of the
+// HashMap since. The entry is found by the address of its slot rather than by
+// comparing keys, so removing a key that was just looked up does not compare
+// it again. Returns NULL if no entry has the slot.
+void *hashmap_remove_slot(HashMap *map, void **slot, uint64_t hash);
+
+// Frees a key handed out by hashmap_remove_entry.
+void hashmap_key_free(char *key);
+
+// Removes every entry from the HashMap while keeping its buckets allocated.
+// Keys are freed and free_value is called on each value unless it is NULL, in
+// which case ownership of the values must have been taken by the caller. An
+// ordered HashMap calls free_value in insertion order, so that values are freed
+// in the same order every time. An arena is emptied but keeps its largest block
+// for the entries to come.
+void hashmap_clear(HashMap *map, void (*free_value)(void *value));
+
 // Frees the HashMap and all its entries, setting the pointer to map to NULL.
 // Will also free all keys and values.
 void hashmap_free(HashMap **map);
+
+// Frees the HashMap and all its entries like hashmap_free, but calls free_value
+// on each value instead of free. If free_value is NULL, values are not freed
+// and ownership of them must have been taken by the caller.
+void hashmap_free_with(HashMap **map, void (*free_value)(void *value));
+
+// Creates a new iterator over the entries of the HashMap. The HashMap must not
+// be modified while the iterator is in use, except through
+// hashmap_iter_remove.
+HashMapIter *hashmap_iter_new(const HashMap *map);
+
+// Advances the iterator, storing the key, its length, and the value of the next
+// entry in key, key_len, and value. Returns false once every entry has been
+// visited.
+bool hashmap_iter_next(HashMapIter *iter, const char **key, size_t *key_len,
+                       void **value);
+
+// Deletes the entry last visited by the iterator from the HashMap it iterates
+// over and returns the value, handing ownership of the value to the caller.
+// Returns NULL if no entry has been visited since the last call to
+// hashmap_iter_next or if map is not the HashMap being iterated over.
+void *hashmap_iter_remove(HashMap *map, HashMapIter *iter);
+
+// Frees the iterator, setting the pointer to iter to NULL. The HashMap it was
+// iterating over is left untouched.
+void hashmap_iter_free(HashMapIter **iter);
+
+// Stores the key, its length, and the value of the entry at the given position
+// in key, key_len, and value, and moves the position past it. The buckets are
+// walked in order even if the HashMap is ordered. Returns false once there are
+// no entries left after the position.
+bool hashmap_position_next(const HashMap *map, HashMapPosition *position,
+                           const char **key, size_t *key_len, void **value);
@@ -0,0 +1,885 @@
+//! Iterators over the entries of a [`HashMap`].
+//!
+//! Keys inserted as bytes or integers need not be valid UTF-8, yet most
+//! iterators yield string keys. None of them panic because of it: the borrowing
+//! iterators [`Iter`], [`Keys`], and [`IterMut`] skip entries whose keys are
+//! not valid UTF-8, while the owning iterators [`IntoIter`] and [`Drain`]
+//! convert such keys lossily, replacing invalid sequences with
+//! [`char::REPLACEMENT_CHARACTER`], so that no value is lost. [`IterBytes`] and
+//! the value iterators yield every entry regardless of its key.
+
+use alloc::{boxed::Box, string::String};
+use core::{
+    ffi::{c_char, c_void},
+    iter::FusedIterator,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ptr, str,
+};
+
+use crate::{hashmap, HashMap};
+
+/// Convert a key yielded by the C iterator into a string slice, or `None` if
+/// it was inserted as bytes and is not valid UTF-8.
+///
+/// # Safety
+/// The key must point to memory that stays valid for `'a`.
+pub(crate) unsafe fn key_str<'a>(key: *const [u8]) -> Option<&'a str> {
+    str::from_utf8(&*key).ok()
+}
+
+/// Copy a key into an owned string, replacing any invalid UTF-8 with
+/// [`char::REPLACEMENT_CHARACTER`].
+///
+/// # Safety
+/// The key must point to valid memory.
+pub(crate) unsafe fn key_string(key: *const [u8]) -> String {
+    String::from_utf8_lossy(&*key).into_owned()
+}
+
+/// Untyped iterator over the key and value pointers of a C hashmap that the
+/// typed iterators are built on. It frees the C iterator when dropped.
+pub(crate) struct RawIter {
+    /// Pointer to the C iterator walking the buckets of the hashmap
+    ptr: *mut hashmap::HashMapIter,
+    /// Number of entries that have not been yielded yet
+    remaining: usize,
+}
+
+impl RawIter {
+    /// Create a new iterator over the entries of the given C hashmap.
+    ///
+    /// # Safety
+    /// The hashmap must stay valid and unmodified while the iterator is in use.
+    pub(crate) unsafe fn new(map: *const hashmap::HashMap) -> Self {
+        Self {
+            ptr: hashmap::hashmap_iter_new(map),
+            remaining: hashmap::hashmap_len(map),
+        }
+    }
+
+    /// Remove the entry last yielded by the iterator from the hashmap,
+    /// returning its value pointer, or null if there is no such entry.
+    ///
+    /// # Safety
+    /// The hashmap must be the one the iterator was created for and no
+    /// references to the removed entry may be used afterwards.
+    pub(crate) unsafe fn remove(&mut self, map: *mut hashmap::HashMap) -> *mut c_void {
+        hashmap::hashmap_iter_remove(map, self.ptr)
+    }
+}
+
+impl Iterator for RawIter {
+    type Item = (*const [u8], *mut c_void);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut key: *const c_char = ptr::null();
+        let mut key_len = 0;
+        let mut value: *mut c_void = ptr::null_mut();
+        if unsafe { hashmap::hashmap_iter_next(self.ptr, &mut key, &mut key_len, &mut value) } {
+            self.remaining -= 1;
+            Some((ptr::slice_from_raw_parts(key as *const u8, key_len), value))
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl Drop for RawIter {
+    fn drop(&mut self) {
+        unsafe { hashmap::hashmap_iter_free(&mut self.ptr as *mut *mut _) }
+    }
+}
+
+/// Iterator over the key-value pairs of a [`HashMap`], created by
+/// [`HashMap::iter`]. Entries are yielded bucket by bucket, so the order is
+/// arbitrary and does not reflect the order of insertion, unless the hashmap
+/// was created by [`HashMap::ordered`]. Entries whose keys
+/// are not valid UTF-8 are skipped, which is why the iterator cannot know its
+/// exact length; [`IterBytes`] yields every entry.
+pub struct Iter<'a, V> {
+    /// Iterator over the raw entries of the hashmap
+    raw: RawIter,
+    /// Phantom data tying the yielded references to the borrow of the hashmap
+    value: PhantomData<&'a V>,
+}
+
+impl<'a, V> Iter<'a, V> {
+    /// Create a new iterator over the entries of the given hashmap.
+    pub(crate) fn new(map: &'a HashMap<V>) -> Self {
+        Self {
+            raw: unsafe { RawIter::new(map.ptr) },
+            value: PhantomData,
+        }
+    }
+
+    /// Advance the iterator like [`Iterator::next`], but yield the key as raw
+    /// bytes so that it never has to be valid UTF-8.
+    pub(crate) fn next_bytes(&mut self) -> Option<(&'a [u8], &'a V)> {
+        let (key, value) = self.raw.next()?;
+        unsafe { Some((&*key, &*(value as *const V))) }
+    }
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (&'a str, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, value) = self.next_bytes()?;
+            if let Some(key) = unsafe { key_str(key) } {
+                return Some((key, value));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.raw.size_hint().1)
+    }
+}
+
+impl<V> FusedIterator for Iter<'_, V> {}
+
+/// Iterator over the key-value pairs of a [`HashMap`] with keys as raw bytes,
+/// created by [`HashMap::iter_bytes`]. Unlike [`Iter`], it yields every entry
+/// no matter what its key contains.
+pub struct IterBytes<'a, V> {
+    /// Iterator over the entries of the hashmap
+    inner: Iter<'a, V>,
+}
+
+impl<'a, V> IterBytes<'a, V> {
+    /// Create a new iterator over the entries of the given hashmap.
+    pub(crate) fn new(map: &'a HashMap<V>) -> Self {
+        Self {
+            inner: Iter::new(map),
+        }
+    }
+}
+
+impl<'a, V> Iterator for IterBytes<'a, V> {
+    type Item = (&'a [u8], &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next_bytes()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.raw.size_hint()
+    }
+}
+
+impl<V> ExactSizeIterator for IterBytes<'_, V> {}
+
+impl<V> FusedIterator for IterBytes<'_, V> {}
+
+/// Iterator over the keys of a [`HashMap`], created by [`HashMap::keys`]. The
+/// order of iteration is arbitrary and, as with [`Iter`], keys that are not
+/// valid UTF-8 are skipped.
+pub struct Keys<'a, V> {
+    /// Iterator over the entries of the hashmap
+    inner: Iter<'a, V>,
+}
+
+impl<'a, V> Keys<'a, V> {
+    /// Create a new iterator over the keys of the given hashmap.
+    pub(crate) fn new(map: &'a HashMap<V>) -> Self {
+        Self {
+            inner: Iter::new(map),
+        }
+    }
+}
+
+impl<'a, V> Iterator for Keys<'a, V> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<V> FusedIterator for Keys<'_, V> {}
+
+/// Iterator over the values of a [`HashMap`], created by [`HashMap::values`].
+/// The order of iteration is arbitrary.
+pub struct Values<'a, V> {
+    /// Iterator over the entries of the hashmap
+    inner: Iter<'a, V>,
+}
+
+impl<'a, V> Values<'a, V> {
+    /// Create a new iterator over the values of the given hashmap.
+    pub(crate) fn new(map: &'a HashMap<V>) -> Self {
+        Self {
+            inner: Iter::new(map),
+        }
+    }
+}
+
+impl<'a, V> Iterator for Values<'a, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next_bytes().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.raw.size_hint()
+    }
+}
+
+impl<V> ExactSizeIterator for Values<'_, V> {}
+
+impl<V> FusedIterator for Values<'_, V> {}
+
+/// Iterator over the keys of a [`HashMap`] paired with mutable references to
+/// their values, created by [`HashMap::iter_mut`]. The order of iteration is
+/// arbitrary and, as with [`Iter`], keys that are not valid UTF-8 are skipped.
+pub struct IterMut<'a, V> {
+    /// Iterator over the raw entries of the hashmap
+    raw: RawIter,
+    /// Phantom data tying the yielded references to the mutable borrow of the
+    /// hashmap, which keeps it from being resized while they are alive
+    value: PhantomData<&'a mut V>,
+}
+
+impl<'a, V> IterMut<'a, V> {
+    /// Create a new iterator over the entries of the given hashmap.
+    pub(crate) fn new(map: &'a mut HashMap<V>) -> Self {
+        Self {
+            raw: unsafe { RawIter::new(map.ptr) },
+            value: PhantomData,
+        }
+    }
+}
+
+impl<'a, V> Iterator for IterMut<'a, V> {
+    type Item = (&'a str, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Every entry owns a distinct allocation and is visited exactly once, so
+        // the mutable references handed out never alias.
+        loop {
+            let (key, value) = self.raw.next()?;
+            if let Some(key) = unsafe { key_str(key) } {
+                return unsafe { Some((key, &mut *(value as *mut V))) };
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.raw.size_hint().1)
+    }
+}
+
+impl<V> FusedIterator for IterMut<'_, V> {}
+
+/// Iterator over mutable references to the values of a [`HashMap`], created by
+/// [`HashMap::values_mut`]. The order of iteration is arbitrary.
+pub struct ValuesMut<'a, V> {
+    /// Iterator over the raw entries of the hashmap
+    raw: RawIter,
+    /// Phantom data tying the yielded references to the mutable borrow of the
+    /// hashmap
+    value: PhantomData<&'a mut V>,
+}
+
+impl<'a, V> ValuesMut<'a, V> {
+    /// Create a new iterator over the values of the given hashmap.
+    pub(crate) fn new(map: &'a mut HashMap<V>) -> Self {
+        Self {
+            raw: unsafe { RawIter::new(map.ptr) },
+            value: PhantomData,
+        }
+    }
+}
+
+impl<'a, V> Iterator for ValuesMut<'a, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // As with IterMut, each value is yielded once. The key is skipped
+        // without being checked for valid UTF-8.
+        let (_, value) = self.raw.next()?;
+        unsafe { Some(&mut *(value as *mut V)) }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.raw.size_hint()
+    }
+}
+
+impl<V> ExactSizeIterator for ValuesMut<'_, V> {}
+
+impl<V> FusedIterator for ValuesMut<'_, V> {}
+
+/// Owning iterator over the key-value pairs of a [`HashMap`], created by its
+/// [`IntoIterator`] implementation. Values are moved out of the hashmap as they
+/// are yielded and the underlying C hashmap is freed once the iterator is
+/// dropped, or only emptied if [`HashMap::set_free_on_drop`] turned freeing
+/// off. As with [`Iter`], the order of iteration is arbitrary. Keys that
+/// are not valid UTF-8 are converted lossily rather than skipped.
+pub struct IntoIter<V> {
+    /// Pointer to the C hashmap being consumed
+    map: *mut hashmap::HashMap,
+    /// Iterator over the raw entries of the hashmap, which must be freed before
+    /// the hashmap itself
+    raw: ManuallyDrop<RawIter>,
+    /// Whether the C hashmap is freed once consumed or only emptied, following
+    /// [`HashMap::set_free_on_drop`]
+    free: bool,
+    /// Phantom data to show we own the values of type V
+    value: PhantomData<V>,
+}
+
+impl<V> IntoIter<V> {
+    /// Create a new owning iterator, taking over the C hashmap from the given
+    /// hashmap so that its [`Drop`] implementation never runs.
+    pub(crate) fn new(map: HashMap<V>) -> Self {
+        let mut map = ManuallyDrop::new(map);
+        // Nothing resizes the hashmap while it is consumed, so its resize
+        // callback can be dropped right away.
+        unsafe { hashmap::hashmap_set_resize_callback(map.ptr, None, ptr::null_mut()) };
+        drop(map.on_resize.take());
+        Self {
+            map: map.ptr,
+            raw: ManuallyDrop::new(unsafe { RawIter::new(map.ptr) }),
+            free: map.free_on_drop,
+            value: PhantomData,
+        }
+    }
+
+    /// Advance the iterator like [`Iterator::next`], but borrow the key as raw
+    /// bytes instead of copying it into a string.
+    pub(crate) fn next_bytes(&mut self) -> Option<(&[u8], Box<V>)> {
+        // The keys are only freed along with the C hashmap when the iterator
+        // is dropped, which the borrow of the iterator rules out.
+        let (key, value) = self.raw.next()?;
+        unsafe { Some((&*key, Box::from_raw(value as *mut V))) }
+    }
+}
+
+impl<V> Iterator for IntoIter<V> {
+    type Item = (String, Box<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.raw.next()?;
+        unsafe { Some((key_string(key), Box::from_raw(value as *mut V))) }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.raw.size_hint()
+    }
+}
+
+impl<V> ExactSizeIterator for IntoIter<V> {}
+
+impl<V> FusedIterator for IntoIter<V> {}
+
+impl<V> Drop for IntoIter<V> {
+    fn drop(&mut self) {
+        // Drop any values that were never yielded so that by the time the C
+        // hashmap is freed, every value has been moved out of it. The keys are
+        // skipped since they might not be valid UTF-8.
+        for (_, value) in &mut *self.raw {
+            unsafe { drop(Box::from_raw(value as *mut V)) }
+        }
+        unsafe {
+            ManuallyDrop::drop(&mut self.raw);
+            if self.free {
+                hashmap::hashmap_free_with(&mut self.map as *mut *mut _, None);
+            } else {
+                hashmap::hashmap_clear(self.map, None);
+            }
+        }
+    }
+}
+
+/// Owning iterator over the keys of a [`HashMap`], created by
+/// [`HashMap::into_keys`]. Values are dropped as their keys are yielded and
+/// keys that are not valid UTF-8 are converted lossily, as with [`IntoIter`].
+pub struct IntoKeys<V> {
+    /// Owning iterator over the entries of the hashmap
+    inner: IntoIter<V>,
+}
+
+impl<V> IntoKeys<V> {
+    /// Create a new owning iterator over the keys of the given hashmap.
+    pub(crate) fn new(map: HashMap<V>) -> Self {
+        Self {
+            inner: IntoIter::new(map),
+        }
+    }
+}
+
+impl<V> Iterator for IntoKeys<V> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<V> ExactSizeIterator for IntoKeys<V> {}
+
+impl<V> FusedIterator for IntoKeys<V> {}
+
+/// Owning iterator over the values of a [`HashMap`], created by
+/// [`HashMap::into_values`]. The keys are never copied out of the hashmap.
+pub struct IntoValues<V> {
+    /// Owning iterator over the entries of the hashmap
+    inner: IntoIter<V>,
+}
+
+impl<V> IntoValues<V> {
+    /// Create a new owning iterator over the values of the given hashmap.
+    pub(crate) fn new(map: HashMap<V>) -> Self {
+        Self {
+            inner: IntoIter::new(map),
+        }
+    }
+}
+
+impl<V> Iterator for IntoValues<V> {
+    type Item = Box<V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next_bytes().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<V> ExactSizeIterator for IntoValues<V> {}
+
+impl<V> FusedIterator for IntoValues<V> {}
+
+/// Draining iterator over the key-value pairs of a [`HashMap`], created by
+/// [`HashMap::drain`]. Each pair is removed from the hashmap as it is yielded
+/// and any pairs left over are dropped along with the iterator, so the hashmap
+/// is always empty afterwards. Its buckets are kept for reuse. Like
+/// [`IntoIter`], it converts keys that are not valid UTF-8 lossily.
+pub struct Drain<'a, V> {
+    /// Iterator over the raw entries of the hashmap
+    raw: RawIter,
+    /// Hashmap being drained
+    map: &'a mut HashMap<V>,
+}
+
+impl<'a, V> Drain<'a, V> {
+    /// Create a new draining iterator over the entries of the given hashmap.
+    pub(crate) fn new(map: &'a mut HashMap<V>) -> Self {
+        Self {
+            raw: unsafe { RawIter::new(map.ptr) },
+            map,
+        }
+    }
+
+    /// Remove the entry last yielded by the raw iterator from the hashmap and
+    /// return its value.
+    ///
+    /// # Safety
+    /// The raw iterator must have just yielded an entry.
+    unsafe fn remove(&mut self) -> Box<V> {
+        self.map.len -= 1;
+        Box::from_raw(self.raw.remove(self.map.ptr) as *mut V)
+    }
+}
+
+impl<'a, V> Iterator for Drain<'a, V> {
+    type Item = (String, Box<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, _) = self.raw.next()?;
+        unsafe {
+            // The key is freed along with the entry, so it has to be copied out
+            // before the entry is removed.
+            let key = key_string(key);
+            Some((key, self.remove()))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.raw.size_hint()
+    }
+}
+
+impl<'a, V> ExactSizeIterator for Drain<'a, V> {}
+
+impl<'a, V> FusedIterator for Drain<'a, V> {}
+
+impl<'a, V> Drop for Drain<'a, V> {
+    fn drop(&mut self) {
+        // Remove the remaining entries without looking at their keys, which
+        // might not be valid UTF-8.
+        while self.raw.next().is_some() {
+            unsafe { drop(self.remove()) }
+        }
+    }
+}
+
+/// Iterator that removes and yields the key-value pairs of a [`HashMap`] for
+/// which a predicate returns true, created by [`HashMap::extract_if`]. Pairs
+/// are only removed as the iterator reaches them, so dropping it early leaves
+/// the rest of the hashmap untouched. Keys that are not valid UTF-8 are
+/// converted lossily, both for the predicate and for the yielded pairs.
+pub struct ExtractIf<'a, V, F> {
+    /// Iterator over the raw entries of the hashmap
+    raw: RawIter,
+    /// Hashmap the pairs are extracted from
+    map: &'a mut HashMap<V>,
+    /// Predicate deciding which pairs are extracted
+    pred: F,
+}
+
+impl<'a, V, F: FnMut(&str, &mut V) -> bool> ExtractIf<'a, V, F> {
+    /// Create a new extracting iterator over the entries of the given hashmap.
+    pub(crate) fn new(map: &'a mut HashMap<V>, pred: F) -> Self {
+        Self {
+            raw: unsafe { RawIter::new(map.ptr) },
+            map,
+            pred,
+        }
+    }
+}
+
+impl<V, F: FnMut(&str, &mut V) -> bool> Iterator for ExtractIf<'_, V, F> {
+    type Item = (String, Box<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, value) = self.raw.next()?;
+            unsafe {
+                let key = String::from_utf8_lossy(&*key);
+                if (self.pred)(&key, &mut *(value as *mut V)) {
+                    // The key is freed along with the entry, so it has to be
+                    // copied out before the entry is removed.
+                    let key = key.into_owned();
+                    self.map.len -= 1;
+                    let value = Box::from_raw(self.raw.remove(self.map.ptr) as *mut V);
+                    return Some((key, value));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.raw.size_hint().1)
+    }
+}
+
+impl<V, F: FnMut(&str, &mut V) -> bool> FusedIterator for ExtractIf<'_, V, F> {}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{tests::Counted, HashMap};
+
+    #[test]
+    fn test_iter_empty() {
+        let map = HashMap::<i32>::new();
+        assert_eq!(map.iter().next(), None);
+        assert_eq!(map.iter_bytes().len(), 0);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(&i.to_string(), i);
+        }
+        map.remove("42");
+
+        let mut entries: Vec<_> = map.iter().map(|(k, v)| (k.to_string(), *v)).collect();
+        entries.sort_by_key(|&(_, v)| v);
+        let expected: Vec<_> = (0..100)
+            .filter(|&i| i != 42)
+            .map(|i| (i.to_string(), i))
+            .collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn test_iter_size_hint() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        map.insert("bar", 2);
+        let mut iter = map.iter_bytes();
+        assert_eq!(iter.len(), 2);
+        iter.next();
+        assert_eq!(iter.len(), 1);
+        iter.next();
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+
+        let mut iter = map.iter();
+        assert_eq!(iter.size_hint(), (0, Some(2)));
+        iter.next();
+        assert_eq!(iter.size_hint(), (0, Some(1)));
+    }
+
+    #[test]
+    fn test_non_utf8_keys() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        map.insert_bytes(&[0xff, b'o'], 2);
+
+        assert_eq!(map.iter().collect::<Vec<_>>(), [("foo", &1)]);
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["foo"]);
+        assert_eq!(map.iter_mut().count(), 1);
+        assert_eq!(map.values().sum::<i32>(), 3);
+        let mut keys: Vec<_> = map.iter_bytes().map(|(k, _)| k).collect();
+        keys.sort();
+        assert_eq!(keys, [&b"foo"[..], &[0xff, b'o'][..]]);
+
+        let mut seen = Vec::new();
+        map.retain(|key, _| {
+            seen.push(key.to_string());
+            true
+        });
+        seen.sort();
+        assert_eq!(seen, ["foo", "\u{fffd}o"]);
+
+        let mut entries: Vec<_> = map.clone().into_iter().map(|(k, v)| (k, *v)).collect();
+        entries.sort();
+        assert_eq!(
+            entries,
+            [("foo".to_string(), 1), ("\u{fffd}o".to_string(), 2)]
+        );
+        let mut entries: Vec<_> = map.drain().map(|(k, v)| (k, *v)).collect();
+        entries.sort();
+        assert_eq!(
+            entries,
+            [("foo".to_string(), 1), ("\u{fffd}o".to_string(), 2)]
+        );
+
+        // Merging keeps byte keys intact instead of going through strings
+        let mut other = HashMap::new();
+        other.insert_bytes(&[0xff], 3);
+        map.merge(other, |_, a, b| a + b);
+        assert_eq!(map.get_bytes(&[0xff]), Some(&3));
+        assert_eq!(map.any_entry(), None);
+    }
+
+    #[test]
+    fn test_keys_after_resize() {
+        let mut map = HashMap::with_buckets(1);
+        for i in 0..1000 {
+            map.insert(&i.to_string(), i);
+        }
+
+        let mut keys: Vec<_> = map.keys().map(|k| k.parse::<i32>().unwrap()).collect();
+        keys.sort();
+        assert_eq!(keys, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_values() {
+        let mut map = HashMap::new();
+        for i in 1..=10 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.values().sum::<i32>(), 55);
+    }
+
+    #[test]
+    fn test_values_mut() {
+        let mut map = HashMap::new();
+        for i in 1..=10 {
+            map.insert(&i.to_string(), i);
+        }
+        for value in map.values_mut() {
+            *value *= 2;
+        }
+        assert_eq!(map.values().sum::<i32>(), 110);
+        assert_eq!(map.get("5"), Some(&10));
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut map = HashMap::new();
+        for i in 1..=10 {
+            map.insert(&i.to_string(), i);
+        }
+        for (key, value) in &mut map {
+            *value += key.parse::<i32>().unwrap();
+        }
+        assert_eq!(map.iter_mut().count(), 10);
+        assert_eq!(map.values().sum::<i32>(), 110);
+        assert_eq!(map.get("5"), Some(&10));
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(&i.to_string(), i);
+        }
+
+        let mut entries: Vec<_> = map.into_iter().collect();
+        entries.sort_by_key(|(_, v)| **v);
+        let expected: Vec<_> = (0..100).map(|i| (i.to_string(), Box::new(i))).collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn test_into_iter_partial() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        for i in 0..10 {
+            map.insert(&i.to_string(), Counted(drops.clone()));
+        }
+
+        let mut iter = map.into_iter();
+        let (_, first) = iter.next().unwrap();
+        assert_eq!(iter.len(), 9);
+        drop(iter);
+        assert_eq!(drops.get(), 9);
+        drop(first);
+        assert_eq!(drops.get(), 10);
+    }
+
+    #[test]
+    fn test_into_keys_and_values() {
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(&i.to_string(), i);
+        }
+        let mut keys: Vec<_> = map.clone().into_keys().collect();
+        keys.sort_by_key(|k| k.parse::<i32>().unwrap());
+        assert_eq!(keys, (0..100).map(|i| i.to_string()).collect::<Vec<_>>());
+        let mut values: Vec<_> = map.into_values().map(|v| *v).collect();
+        values.sort();
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+
+        let drops = Rc::new(Cell::new(0));
+        let counted = || {
+            let mut map = HashMap::new();
+            for i in 0..10 {
+                map.insert(&i.to_string(), Counted(drops.clone()));
+            }
+            map
+        };
+        let mut keys = counted().into_keys();
+        keys.next();
+        assert_eq!(drops.get(), 1);
+        assert_eq!(keys.len(), 9);
+        drop(keys);
+        assert_eq!(drops.get(), 10);
+        let mut values = counted().into_values();
+        let first = values.next().unwrap();
+        drop(values);
+        assert_eq!(drops.get(), 19);
+        drop(first);
+        assert_eq!(drops.get(), 20);
+    }
+
+    #[test]
+    fn test_extract_if() {
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(&i.to_string(), i);
+        }
+        let mut evens: Vec<_> = map
+            .extract_if(|_, value| {
+                *value += 1;
+                *value % 2 == 1
+            })
+            .map(|(k, v)| (k, *v))
+            .collect();
+        evens.sort_by_key(|&(_, v)| v);
+        let expected: Vec<_> = (0..100)
+            .step_by(2)
+            .map(|i| (i.to_string(), i + 1))
+            .collect();
+        assert_eq!(evens, expected);
+        assert_eq!(map.len(), 50);
+        assert!(map.iter().all(|(k, v)| k.parse::<i32>().unwrap() + 1 == *v));
+    }
+
+    #[test]
+    fn test_extract_if_partial() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        for i in 0..10 {
+            map.insert(&i.to_string(), Counted(drops.clone()));
+        }
+
+        let mut iter = map.extract_if(|_, _| true);
+        let (key, first) = iter.next().unwrap();
+        drop(iter);
+        assert_eq!(drops.get(), 0);
+        assert_eq!(map.len(), 9);
+        assert!(!map.contains_key(&key));
+        drop(first);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_drain_filter() {
+        let mut map = HashMap::new();
+        for i in 0..10 {
+            map.insert(&i.to_string(), i);
+        }
+        let mut removed: Vec<_> = map.drain_filter(|_, value| *value < 3).collect();
+        removed.sort();
+        let expected: Vec<_> = (0..3).map(|i| (i.to_string(), Box::new(i))).collect();
+        assert_eq!(removed, expected);
+        assert_eq!(map.len(), 7);
+        assert!(!map.contains_key("0"));
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(&i.to_string(), i);
+        }
+        let buckets = map.bucket_count();
+
+        let mut entries: Vec<_> = map.drain().map(|(k, v)| (k, *v)).collect();
+        entries.sort_by_key(|&(_, v)| v);
+        let expected: Vec<_> = (0..100).map(|i| (i.to_string(), i)).collect();
+        assert_eq!(entries, expected);
+        assert!(map.is_empty());
+        assert_eq!(map.bucket_count(), buckets);
+
+        map.insert("foo", 1);
+        assert_eq!(map.get("foo"), Some(&1));
+    }
+
+    #[test]
+    fn test_drain_partial() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        for i in 0..10 {
+            map.insert(&i.to_string(), Counted(drops.clone()));
+        }
+
+        let mut drain = map.drain();
+        let (key, first) = drain.next().unwrap();
+        assert_eq!(drain.len(), 9);
+        drop(drain);
+        assert_eq!(drops.get(), 9);
+        assert!(map.is_empty());
+        assert!(!map.contains_key(&key));
+        drop(first);
+        assert_eq!(drops.get(), 10);
+    }
+}
@@ -0,0 +1,135 @@
+//! Types that can be used as keys of a [`HashMap`](crate::HashMap).
+
+use alloc::{string::String, vec::Vec};
+
+/// Type whose values can be used as keys of a [`HashMap`](crate::HashMap). The
+/// hashmap only ever sees the bytes of a key, so keys of different types with
+/// the same bytes refer to the same entry, just as a string key and a byte key
+/// do.
+///
+/// Integers are stored as their little-endian bytes, so they don't need to be
+/// converted to strings first. Since those bytes are rarely valid UTF-8, use
+/// [`HashMap::iter_bytes`](crate::HashMap::iter_bytes) to iterate over a
+/// hashmap with integer keys.
+///
+/// ```
+/// use bindgen_hashmap::HashMap;
+///
+/// let mut map = HashMap::new();
+/// map.insert(&42u64, "answer");
+/// assert_eq!(map.get(&42u64), Some(&"answer"));
+/// assert_eq!(map.get_bytes(&42u64.to_le_bytes()), Some(&"answer"));
+/// ```
+pub trait HashMapKey {
+    /// View of the bytes of the key
+    type Bytes<'a>: AsRef<[u8]>
+    where
+        Self: 'a;
+
+    /// Returns the bytes the key is stored as, or `None` if it can never be
+    /// stored. String keys containing an interior nul byte are rejected, since
+    /// the `&str` methods of the hashmap rely on no string key containing one.
+    fn key_bytes(&self) -> Option<Self::Bytes<'_>>;
+}
+
+impl<K: HashMapKey + ?Sized> HashMapKey for &K {
+    type Bytes<'a>
+        = K::Bytes<'a>
+    where
+        Self: 'a;
+
+    fn key_bytes(&self) -> Option<Self::Bytes<'_>> {
+        (**self).key_bytes()
+    }
+}
+
+impl HashMapKey for str {
+    type Bytes<'a> = &'a [u8];
+
+    fn key_bytes(&self) -> Option<Self::Bytes<'_>> {
+        (!self.contains('\0')).then_some(self.as_bytes())
+    }
+}
+
+impl HashMapKey for String {
+    type Bytes<'a> = &'a [u8];
+
+    fn key_bytes(&self) -> Option<Self::Bytes<'_>> {
+        self.as_str().key_bytes()
+    }
+}
+
+impl HashMapKey for [u8] {
+    type Bytes<'a> = &'a [u8];
+
+    fn key_bytes(&self) -> Option<Self::Bytes<'_>> {
+        Some(self)
+    }
+}
+
+impl<const N: usize> HashMapKey for [u8; N] {
+    type Bytes<'a> = &'a [u8];
+
+    fn key_bytes(&self) -> Option<Self::Bytes<'_>> {
+        Some(self)
+    }
+}
+
+impl HashMapKey for Vec<u8> {
+    type Bytes<'a> = &'a [u8];
+
+    fn key_bytes(&self) -> Option<Self::Bytes<'_>> {
+        Some(self)
+    }
+}
+
+/// Implement [`HashMapKey`] for integer types using their little-endian bytes.
+macro_rules! impl_integer_key {
+    ($($int:ty),*) => {
+        $(
+            impl HashMapKey for $int {
+                type Bytes<'a> = [u8; core::mem::size_of::<$int>()];
+
+                fn key_bytes(&self) -> Option<Self::Bytes<'_>> {
+                    Some(self.to_le_bytes())
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_key!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+#[cfg(test)]
+mod tests {
+    use crate::HashMap;
+
+    #[test]
+    fn test_integer_keys() {
+        let mut map = HashMap::new();
+        for i in 0..1000u64 {
+            map.insert(&i, i * 2);
+        }
+        assert_eq!(map.len(), 1000);
+        assert_eq!(map.get(&500u64), Some(&1000));
+        assert_eq!(map.get(&500u32), None);
+        assert_eq!(map.get(&0u64), Some(&0));
+        *map.get_mut(&1u64).unwrap() = 7;
+        assert_eq!(map.remove(&1u64), Some(Box::new(7)));
+        assert!(!map.contains_key(&1u64));
+        assert!(map.contains_key(&2u64.to_le_bytes()));
+    }
+
+    #[test]
+    fn test_string_and_byte_keys() {
+        let mut map = HashMap::new();
+        let owned = String::from("foo");
+        map.insert(&owned, 1);
+        assert_eq!(map.get("foo"), Some(&1));
+        assert_eq!(map.get(b"foo"), Some(&1));
+        assert_eq!(map.get(&b"foo".to_vec()), Some(&1));
+        assert_eq!(map.insert(b"f\0o", 2), None);
+        assert_eq!(map.get(b"f\0o"), Some(&2));
+        assert_eq!(map.get("f\0o"), None);
+    }
+}
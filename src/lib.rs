@@ -1,45 +1,392 @@
 //! Rust wrapper for a basic implementation of a hashmap in C
+//!
+//! The `std` feature is enabled by default. Without it the crate is `no_std`
+//! and only needs `alloc`, in which case the C hashmap allocates through the
+//! global Rust allocator instead of the libc one. Hashmaps are then keyed with
+//! a fixed secret key, since there is no source of randomness, and conversions
+//! to and from the standard library `HashMap` are unavailable.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![warn(
     missing_docs,
     clippy::missing_docs_in_private_items,
     rustdoc::broken_intra_doc_links
 )]
-use std::{
-    ffi::{c_void, CString},
+extern crate alloc;
+
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::{
+    cmp::Ordering,
+    ffi::{c_char, c_void, CStr},
+    fmt::{self, Debug},
+    hash::{Hash, Hasher},
     marker::PhantomData,
+    mem::{self, size_of, ManuallyDrop},
+    ops::Index,
+    ptr, str,
 };
+#[cfg(feature = "std")]
+use std::{collections::hash_map::RandomState, hash::BuildHasher};
 
+mod builder;
+mod cursor;
+mod entry;
+mod error;
+mod frozen;
 mod hashmap;
+mod iter;
+mod key;
+mod multimap;
+#[cfg(not(feature = "std"))]
+mod rust_alloc;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod stats;
+
+pub use builder::HashMapBuilder;
+pub use cursor::{Cursor, CursorPosition};
+pub use entry::{Entry, EntryRef, OccupiedEntry, RawEntryBuilderMut, VacantEntry};
+pub use error::{HashMapError, TryReserveError};
+pub use frozen::FrozenHashMap;
+pub use iter::{
+    Drain, ExtractIf, IntoIter, IntoKeys, IntoValues, Iter, IterBytes, IterMut, Keys, Values,
+    ValuesMut,
+};
+pub use key::HashMapKey;
+pub use multimap::MultiMap;
+pub use stats::BucketStats;
+
+use iter::RawIter;
 
-/// Basic hashmap implementation wrapping the C hashmap. Buckets are doubled
-/// whenever the load factor exceeds 0.75.
+/// Basic hashmap implementation wrapping the C hashmap. Buckets are doubled, or
+/// multiplied by the factor set with [`HashMap::set_growth_factor`], whenever
+/// the load factor would exceed the maximum load factor, which is 0.75 unless
+/// changed with [`HashMap::set_max_load_factor`].
+///
+/// Values dropped by the C side, when the hashmap is cleared or dropped, must
+/// not panic while being dropped. Since a panic cannot unwind through C, it
+/// aborts the process instead. Values handed back to Rust, such as by
+/// [`HashMap::remove`], are dropped by the caller and may panic as usual.
 pub struct HashMap<V> {
     /// Pointer to the C hashmap we're wrapping
     ptr: *mut hashmap::HashMap,
+    /// Number of keys in the C hashmap, kept in sync on every insertion and
+    /// removal so that reading it does not need to cross into C
+    len: usize,
+    /// Callback registered with [`HashMap::on_resize`], boxed a second time so
+    /// that the C hashmap can hold on to it through a thin pointer
+    on_resize: Option<Box<ResizeCallback>>,
+    /// Whether dropping the wrapper frees the C hashmap and its values, which
+    /// only hashmaps adopted through [`HashMap::from_raw`] may want to avoid
+    free_on_drop: bool,
     /// Phantom data to show we're using V even if it's not clear from types
     /// alone because of ffi
     value: PhantomData<V>,
 }
 
+/// Outcome of [`HashMap::replace`], telling a newly inserted key apart from one
+/// whose value was overwritten.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Replaced<V> {
+    /// The key did not exist yet and was inserted.
+    Inserted,
+    /// The key already existed and its old value was replaced.
+    Updated(Box<V>),
+}
+
+/// Callback called with the old and new number of buckets after a resize
+type ResizeCallback = Box<dyn FnMut(usize, usize) + Send>;
+
+/// Call the resize callback passed to the C hashmap as data.
+///
+/// As with [`drop_value`], a panic in the callback aborts rather than unwinding
+/// through C.
+unsafe extern "C" fn call_on_resize(data: *mut c_void, old_buckets: usize, new_buckets: usize) {
+    let callback = &mut *(data as *mut ResizeCallback);
+    callback(old_buckets, new_buckets);
+}
+
+/// Drop a value stored in the C hashmap by reconstructing its box. Handed to the
+/// C side whenever it frees values on our behalf.
+///
+/// Values are always stored as box pointers, which are never null, so the C side
+/// can use null to mean that a key is absent. That holds for zero-sized values
+/// too, whose boxes hold a dangling but non-null pointer and never allocate.
+///
+/// If the value panics while being dropped, the panic would have to unwind
+/// through the C frames that called this function, which is undefined
+/// behavior. Functions with the `"C"` ABI can't unwind, so the panic aborts the
+/// process instead before it reaches C.
+unsafe extern "C" fn drop_value<V>(value: *mut c_void) {
+    drop(Box::from_raw(value as *mut V));
+}
+
+/// Generate a random secret key for SipHash. The randomness comes from the
+/// standard library, which seeds it from the operating system, so the C side
+/// never has to touch any global state to get it.
+#[cfg(feature = "std")]
+fn random_key() -> [u8; 16] {
+    let state = RandomState::new();
+    let mut key = [0; 16];
+    for (i, chunk) in key.chunks_exact_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        chunk.copy_from_slice(&hasher.finish().to_ne_bytes());
+    }
+    key
+}
+
+/// Without the standard library there is no source of randomness, so every
+/// hashmap uses the same all-zero secret key. Such hashmaps are not protected
+/// against collision attacks and should use a custom hasher if that matters.
+#[cfg(not(feature = "std"))]
+fn random_key() -> [u8; 16] {
+    [0; 16]
+}
+
 impl<V> HashMap<V> {
     /// Create a new hashmap with 0 buckets. It will be initialized to 8 buckets
-    /// on first insertion.
+    /// on first insertion, so until then [`HashMap::bucket_count`] and
+    /// [`HashMap::capacity`] return 0. Use [`HashMap::new_eager`] to allocate
+    /// the buckets right away instead.
     pub fn new() -> Self {
         Self::default()
     }
 
-    /// Create a new hashmap with the specified number of buckets.
+    /// Create a new hashmap with the 8 buckets that [`HashMap::new`] only
+    /// allocates on first insertion.
+    pub fn new_eager() -> Self {
+        Self::with_buckets(8)
+    }
+
+    /// Create a new hashmap with the specified number of buckets. Keys are
+    /// spread over the buckets by their hash modulo the number of buckets, so
+    /// it need not be a power of two. With 0 buckets, nothing is allocated
+    /// and the hashmap is the same as one from [`HashMap::new`], getting its
+    /// first 8 buckets on the first insertion.
+    ///
+    /// # Panics
+    /// Panics if the buckets cannot be allocated. Use
+    /// [`HashMap::try_with_buckets`] to handle this instead.
     pub fn with_buckets(buckets: usize) -> Self {
+        Self::try_with_buckets(buckets)
+            .unwrap_or_else(|err| panic!("failed to allocate {buckets} buckets: {err}"))
+    }
+
+    /// Create a new hashmap with the specified number of buckets like
+    /// [`HashMap::with_buckets`], but return an error instead of panicking if
+    /// the buckets cannot be allocated, which includes bucket counts too large
+    /// for the size of their array to be computed.
+    pub fn try_with_buckets(buckets: usize) -> Result<Self, TryReserveError> {
+        if buckets
+            .checked_mul(size_of::<*mut hashmap::Entry>())
+            .is_none()
+        {
+            return Err(TryReserveError::CapacityOverflow);
+        }
+        let ptr = unsafe { hashmap::hashmap_with_buckets(buckets, random_key().as_ptr()) };
+        if ptr.is_null() {
+            return Err(TryReserveError::AllocError);
+        }
+        Ok(Self {
+            ptr,
+            len: 0,
+            on_resize: None,
+            free_on_drop: true,
+            value: PhantomData,
+        })
+    }
+
+    /// Create a new hashmap that can hold at least the given number of keys
+    /// before it needs to grow. As with [`HashMap::reserve`], the number of
+    /// buckets is the smallest power of two of at least 8 that keeps the load
+    /// factor at or below 0.75. A capacity of 0 behaves like [`HashMap::new`].
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut map = Self::new();
+        if capacity > 0 {
+            map.reserve(capacity);
+        }
+        map
+    }
+
+    /// Create a new hashmap whose number of buckets is always a prime number,
+    /// starting with the smallest prime from its table that is at least `min`,
+    /// so never fewer than 11 buckets. Keys are then spread by the remainder of
+    /// dividing their hash by a prime, which copes far better with weak hash
+    /// functions and adversarial keys than a power of two does. Whenever the
+    /// hashmap grows or shrinks, it picks the next fitting prime from the same
+    /// precomputed table, where each prime is roughly `sqrt(2)` times the one
+    /// before.
+    ///
+    /// # Panics
+    /// Panics if the buckets cannot be allocated.
+    pub fn with_prime_buckets(min: usize) -> Self {
+        let ptr = unsafe { hashmap::hashmap_with_prime_buckets(min, random_key().as_ptr()) };
+        assert!(!ptr.is_null(), "failed to allocate {min} buckets");
+        Self {
+            ptr,
+            len: 0,
+            on_resize: None,
+            free_on_drop: true,
+            value: PhantomData,
+        }
+    }
+
+    /// Create a new hashmap that hashes its keys with the given function
+    /// instead of SipHash. The function receives a pointer to the key and its
+    /// length in bytes. The hasher is fixed for the lifetime of the hashmap,
+    /// since keys that were already inserted would otherwise end up in the
+    /// wrong buckets.
+    pub fn with_hasher(hasher: extern "C" fn(*const c_char, usize) -> u64) -> Self {
+        let map = Self::new();
+        unsafe { hashmap::hashmap_set_hasher(map.ptr, Some(hasher)) };
+        map
+    }
+
+    /// Create a new hashmap whose keys ignore ASCII case, so that inserting
+    /// `"Content-Type"` and then getting `"content-type"` finds the value.
+    /// Other characters must match exactly. Keys keep the case they were first
+    /// inserted with, which is what [`HashMap::get_key_value`] and iteration
+    /// return.
+    pub fn case_insensitive() -> Self {
+        let map = Self::new();
+        unsafe { hashmap::hashmap_set_case_insensitive(map.ptr, true) };
+        map
+    }
+
+    /// Create a new hashmap that remembers the order its keys were inserted in,
+    /// so that iteration, [`HashMap::any_entry`], and anything built on them,
+    /// such as [`Debug`] output, are deterministic. Replacing the value of a
+    /// key keeps its position, while a key that is removed and inserted again
+    /// moves to the end. Every entry has room for the two pointers of the
+    /// order list, whether or not the hashmap is ordered, so the only extra
+    /// cost is keeping them up to date.
+    ///
+    /// Dropping or clearing the hashmap also drops its values in insertion
+    /// order, so the destructors of the values run in a reproducible order,
+    /// which helps when tracking down leaks or double frees in them.
+    ///
+    /// ```
+    /// use bindgen_hashmap::HashMap;
+    ///
+    /// let mut map = HashMap::ordered();
+    /// map.insert("b", 1);
+    /// map.insert("a", 2);
+    /// map.insert("c", 3);
+    /// assert_eq!(map.keys().collect::<Vec<_>>(), ["b", "a", "c"]);
+    /// ```
+    pub fn ordered() -> Self {
+        let map = Self::new();
+        unsafe { hashmap::hashmap_set_ordered(map.ptr, true) };
+        map
+    }
+
+    /// Create a new hashmap that allocates its entries and keys from an arena
+    /// instead of one by one, which makes inserting much cheaper. In exchange,
+    /// removing an entry does not free its memory, which is only reclaimed
+    /// when the hashmap is cleared or dropped. This suits hashmaps that are
+    /// built up, used, and dropped as a whole. The values are still boxed and
+    /// dropped individually.
+    ///
+    /// # Panics
+    /// Panics if the arena cannot be allocated.
+    pub fn with_arena() -> Self {
+        let map = Self::new();
+        assert!(
+            unsafe { hashmap::hashmap_use_arena(map.ptr) },
+            "failed to allocate hashmap arena"
+        );
+        map
+    }
+
+    /// Create a new hashmap that keeps its entries directly in an array of
+    /// slots, resolving collisions by linear probing instead of chaining
+    /// entries in linked lists. A lookup then walks neighbouring slots rather
+    /// than following a pointer per entry, which suits lookup-heavy workloads.
+    /// Removing a key shifts the entries probed past it back instead of
+    /// leaving a tombstone, so lookups don't slow down as keys come and go.
+    /// Every method behaves as for any other hashmap, although entries are
+    /// never stored in insertion order or with their keys inline.
+    pub fn with_open_addressing() -> Self {
+        let map = Self::new();
+        // Without buckets, switching allocates nothing and cannot fail
+        unsafe { hashmap::hashmap_use_open_addressing(map.ptr) };
+        map
+    }
+
+    /// Returns a pointer to the underlying C hashmap, which stays owned by the
+    /// wrapper. It is valid until the wrapper is dropped. C code may read the
+    /// hashmap through it as long as the wrapper is not mutably borrowed, but
+    /// any modification has to happen after handing the hashmap over with
+    /// [`HashMap::into_raw`], since the wrapper caches its length.
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.ptr as *mut c_void
+    }
+
+    /// Consume the wrapper without freeing the C hashmap, returning a pointer
+    /// to it. The caller becomes responsible for the hashmap and its values,
+    /// which are pointers created by `Box::<V>::into_raw`. A resize callback
+    /// registered with [`HashMap::on_resize`] is removed and dropped.
+    pub fn into_raw(self) -> *mut c_void {
+        let mut map = ManuallyDrop::new(self);
+        unsafe { hashmap::hashmap_set_resize_callback(map.ptr, None, ptr::null_mut()) };
+        drop(map.on_resize.take());
+        map.ptr as *mut c_void
+    }
+
+    /// Adopt a C hashmap, such as one returned by [`HashMap::into_raw`] or
+    /// created by C code with `hashmap_new`. By default the wrapper takes
+    /// ownership and frees the hashmap and its values when dropped, which
+    /// [`HashMap::set_free_on_drop`] can turn off for hashmaps that stay owned
+    /// by C code.
+    ///
+    /// # Safety
+    /// The pointer must point to a valid C hashmap that nothing else accesses
+    /// while the wrapper is alive, and every value stored in it must be a
+    /// pointer created by `Box::<V>::into_raw`. If the wrapper frees the
+    /// hashmap on drop, it must not be used afterwards.
+    pub unsafe fn from_raw(ptr: *mut c_void) -> Self {
+        let ptr = ptr as *mut hashmap::HashMap;
         Self {
-            ptr: unsafe { hashmap::hashmap_with_buckets(buckets) },
+            ptr,
+            len: hashmap::hashmap_len(ptr),
+            on_resize: None,
+            free_on_drop: true,
             value: PhantomData,
         }
     }
 
+    /// Choose whether dropping the wrapper frees the C hashmap along with its
+    /// values. When turned off, both are left untouched on drop, so ownership
+    /// stays with whoever created the hashmap, and consuming the wrapper with
+    /// [`HashMap::into_iter`] empties the hashmap instead of freeing it. This
+    /// is meant for hashmaps adopted with [`HashMap::from_raw`]; turning it
+    /// off for any other hashmap leaks it.
+    pub fn set_free_on_drop(&mut self, free_on_drop: bool) {
+        self.free_on_drop = free_on_drop;
+    }
+
+    /// Returns a builder for configuring the buckets, load factor, growth factor
+    /// and hasher of a new hashmap all at once.
+    pub fn builder() -> HashMapBuilder<V> {
+        HashMapBuilder::new()
+    }
+
+    /// Create a new hashmap that grows whenever its load factor would exceed
+    /// the given maximum, as set by [`HashMap::set_max_load_factor`].
+    ///
+    /// # Panics
+    /// Panics if the factor is not between 0.1 inclusive and 1 exclusive.
+    pub fn with_max_load_factor(factor: f64) -> Self {
+        let mut map = Self::new();
+        map.set_max_load_factor(factor);
+        map
+    }
+
     /// Returns the number of keys stored in the HashMap.
     pub fn len(&self) -> usize {
-        unsafe { hashmap::hashmap_len(self.ptr) }
+        debug_assert_eq!(self.len, unsafe { hashmap::hashmap_len(self.ptr) });
+        self.len
     }
 
     /// Returns true if the HashMap contains no elements.
@@ -47,131 +394,3343 @@ impl<V> HashMap<V> {
         self.len() == 0
     }
 
-    /// Returns the ratio of keys to buckets in the HashMap.
+    /// Returns the number of keys the HashMap can hold before it grows, which
+    /// is the maximum load factor times the number of buckets, rounded down.
+    pub fn capacity(&self) -> usize {
+        unsafe { hashmap::hashmap_capacity(self.ptr) }
+    }
+
+    /// Returns the number of buckets allocated by the HashMap.
+    pub fn bucket_count(&self) -> usize {
+        unsafe { hashmap::hashmap_bucket_count(self.ptr) }
+    }
+
+    /// Returns the ratio of keys to buckets in the HashMap, or 0 if no buckets
+    /// have been allocated yet.
     pub fn load_factor(&self) -> f64 {
         unsafe { hashmap::hashmap_load_factor(self.ptr) }
     }
 
-    /// Insert a key-value pair into the hashmap. If the key already exists, the
-    /// old value is returned.
-    pub fn insert(&mut self, key: &str, value: Box<V>) -> Option<Box<V>> {
-        unsafe {
-            let old_value = hashmap::hashmap_insert(
-                self.ptr,
-                CString::new(key).ok()?.as_ptr(),
-                Box::into_raw(value) as *mut c_void,
-            );
-            if old_value.is_null() {
-                None
-            } else {
-                Some(Box::from_raw(old_value as *mut _))
+    /// Returns the load factor the HashMap may reach before it grows.
+    pub fn max_load_factor(&self) -> f64 {
+        unsafe { (*self.ptr).max_load_factor }
+    }
+
+    /// Set the load factor the hashmap may reach before it grows. A lower
+    /// maximum trades memory for fewer collisions. If the hashmap is already
+    /// above the new maximum, it grows right away.
+    ///
+    /// # Panics
+    /// Panics if the factor is not between 0.1 inclusive and 1 exclusive.
+    pub fn set_max_load_factor(&mut self, factor: f64) {
+        let valid = unsafe { hashmap::hashmap_set_max_load_factor(self.ptr, factor) };
+        assert!(
+            valid,
+            "max load factor must be at least 0.1 and less than 1, got {factor}"
+        );
+    }
+
+    /// Returns the factor the number of buckets is multiplied by whenever the
+    /// hashmap grows on insertion.
+    pub fn growth_factor(&self) -> f64 {
+        unsafe { (*self.ptr).growth_factor }
+    }
+
+    /// Set the factor the number of buckets is multiplied by whenever the
+    /// hashmap grows on insertion, rounding up. The default of 2 doubles the
+    /// buckets, while a smaller factor such as 1.5 wastes less memory on large
+    /// hashmaps at the cost of growing more often. [`HashMap::reserve`] is not
+    /// affected and still grows straight to the number of buckets it needs.
+    ///
+    /// # Panics
+    /// Panics if the factor is not finite and greater than 1.
+    pub fn set_growth_factor(&mut self, factor: f64) {
+        let valid = unsafe { hashmap::hashmap_set_growth_factor(self.ptr, factor) };
+        assert!(
+            valid,
+            "growth factor must be finite and greater than 1, got {factor}"
+        );
+    }
+
+    /// Returns statistics about how the keys are distributed across the
+    /// buckets, which helps to tell whether the hash function suits the keys.
+    /// Computing them walks every bucket and entry of the hashmap.
+    pub fn bucket_stats(&self) -> BucketStats {
+        let mut stats = hashmap::HashMapStats {
+            buckets: 0,
+            empty_buckets: 0,
+            max_chain_len: 0,
+            mean_chain_len: 0.0,
+        };
+        unsafe { hashmap::hashmap_stats(self.ptr, &mut stats) };
+        stats.into()
+    }
+
+    /// Returns an estimate of the number of bytes the C hashmap has allocated:
+    /// the hashmap itself, its buckets, and one node plus a copy of the key for
+    /// every entry. The boxed values are not counted, nor is the overhead of
+    /// the allocator or the Rust wrapper. Computing it walks every entry.
+    pub fn memory_usage(&self) -> usize {
+        unsafe { hashmap::hashmap_memory_usage(self.ptr) }
+    }
+
+    /// Check the internal invariants of the C hashmap, such as its length
+    /// matching the entries it holds and every entry being where a lookup of
+    /// its key finds it, along with the length kept by the Rust wrapper.
+    /// Returns a description of the first broken invariant found. Walks every
+    /// entry, so it is meant for tests and fuzzing rather than regular use.
+    pub fn validate(&self) -> Result<(), String> {
+        let error = unsafe { hashmap::hashmap_validate(self.ptr) };
+        if !error.is_null() {
+            return Err(unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .into_owned());
+        }
+        let len = unsafe { hashmap::hashmap_len(self.ptr) };
+        if self.len != len {
+            return Err(format!(
+                "the wrapper counts {} keys but the C hashmap {len}",
+                self.len
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns how many entries the hashmap has compared keys against since
+    /// the count was last taken, and resets it to zero. Every entry visited
+    /// while walking a bucket counts as one probe, so a lookup that finds its
+    /// key first in its bucket takes a single probe and one in an empty bucket
+    /// takes none.
+    #[cfg(feature = "probe-stats")]
+    pub fn take_probe_count(&mut self) -> u64 {
+        unsafe { core::mem::take(&mut (*self.ptr).probes) }
+    }
+
+    /// Reserve space for at least the given number of additional keys so that
+    /// they can be inserted without the hashmap growing. The hashmap grows at
+    /// most once, rehashing its existing keys a single time, and never shrinks.
+    ///
+    /// # Panics
+    /// Panics if the space cannot be reserved. Use [`HashMap::try_reserve`] to
+    /// handle this instead.
+    pub fn reserve(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve(additional) {
+            panic!("failed to reserve space for {additional} keys: {err}");
+        }
+    }
+
+    /// Reserve space for at least the given number of additional keys like
+    /// [`HashMap::reserve`], but return an error instead of panicking if the
+    /// space cannot be reserved. On error, the hashmap is left unchanged.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        match unsafe { hashmap::hashmap_reserve(self.ptr, additional) } {
+            hashmap::HashMapStatus_HASHMAP_CAPACITY_OVERFLOW => {
+                Err(TryReserveError::CapacityOverflow)
             }
+            hashmap::HashMapStatus_HASHMAP_ALLOC_FAILED => Err(TryReserveError::AllocError),
+            _ => Ok(()),
         }
     }
 
-    /// Get a reference to the value associated with the given key.
-    pub fn get(&mut self, key: &str) -> Option<&V> {
-        unsafe {
-            let value = hashmap::hashmap_get(self.ptr, CString::new(key).ok()?.as_ptr());
-            if value.is_null() {
-                None
-            } else {
-                Some(&*(value as *const _))
+    /// Reserve space for exactly the given number of additional keys, growing
+    /// to the fewest buckets that hold them without the load factor exceeding
+    /// the maximum load factor instead of rounding up to a power of two. Only
+    /// this reservation is exact: once the hashmap fills up, it grows by its
+    /// growth factor like any other. Like [`HashMap::reserve`], the hashmap
+    /// grows at most once and never shrinks.
+    ///
+    /// # Panics
+    /// Panics if the space cannot be reserved. Use
+    /// [`HashMap::try_reserve_exact`] to handle this instead.
+    pub fn reserve_exact(&mut self, additional: usize) {
+        if let Err(err) = self.try_reserve_exact(additional) {
+            panic!("failed to reserve space for {additional} keys: {err}");
+        }
+    }
+
+    /// Reserve space for exactly the given number of additional keys like
+    /// [`HashMap::reserve_exact`], but return an error instead of panicking if
+    /// the space cannot be reserved. On error, the hashmap is left unchanged.
+    pub fn try_reserve_exact(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        match unsafe { hashmap::hashmap_reserve_exact(self.ptr, additional) } {
+            hashmap::HashMapStatus_HASHMAP_CAPACITY_OVERFLOW => {
+                Err(TryReserveError::CapacityOverflow)
             }
+            hashmap::HashMapStatus_HASHMAP_ALLOC_FAILED => Err(TryReserveError::AllocError),
+            _ => Ok(()),
         }
     }
 
-    /// Get a mutable reference to the value associated with the given key.
+    /// Shrink the hashmap to the smallest power of two number of buckets, but no
+    /// fewer than 8, that holds its keys while keeping the load factor at or
+    /// below the maximum load factor. A hashmap created by
+    /// [`HashMap::with_prime_buckets`] shrinks to the smallest such prime from
+    /// its table instead. A hashmap that has not allocated any buckets yet is
+    /// left untouched.
+    pub fn shrink_to_fit(&mut self) {
+        unsafe { hashmap::hashmap_shrink(self.ptr) }
+    }
+
+    /// Register a callback that is called with the old and new number of
+    /// buckets whenever the hashmap has resized, whether it grew on insertion,
+    /// was reserved, or shrank. Any previously registered callback is
+    /// replaced. Hashmaps without a callback don't pay for one.
     ///
-    /// # Safety
-    /// Since the hashmap does not know if other references to the value exist,
-    /// the safety of this operation is up to the caller.
-    pub unsafe fn get_mut_unchecked(&mut self, key: &str) -> Option<&mut V> {
+    /// The callback must not panic, since a panic can't unwind through the C
+    /// code that calls it and aborts the process instead. Clones of the
+    /// hashmap don't inherit the callback.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use bindgen_hashmap::HashMap;
+    ///
+    /// let resizes = Arc::new(Mutex::new(Vec::new()));
+    /// let mut map = HashMap::new();
+    /// let log = resizes.clone();
+    /// map.on_resize(move |old, new| log.lock().unwrap().push((old, new)));
+    /// for i in 0..7 {
+    ///     map.insert(&i.to_string(), i);
+    /// }
+    /// assert_eq!(*resizes.lock().unwrap(), [(0, 8), (8, 16)]);
+    /// ```
+    pub fn on_resize<F: FnMut(usize, usize) + Send + 'static>(&mut self, f: F) {
+        self.set_resize_callback(Box::new(Box::new(f)));
+    }
+
+    /// Register an already boxed resize callback with the C hashmap and keep it
+    /// alive for as long as the hashmap, replacing any previous callback.
+    fn set_resize_callback(&mut self, mut callback: Box<ResizeCallback>) {
         unsafe {
-            let value = hashmap::hashmap_get(self.ptr, CString::new(key).ok()?.as_ptr());
-            if value.is_null() {
-                None
-            } else {
-                Some(&mut *(value as *mut _))
-            }
+            hashmap::hashmap_set_resize_callback(
+                self.ptr,
+                Some(call_on_resize),
+                &mut *callback as *mut ResizeCallback as *mut c_void,
+            )
+        };
+        self.on_resize = Some(callback);
+    }
+
+    /// Shrink the hashmap like [`HashMap::shrink_to_fit`], but keep enough
+    /// buckets to hold at least `min_capacity` keys without growing. If the
+    /// hashmap already holds more keys than that, it only shrinks as far as its
+    /// keys allow, so no key is ever lost. The hashmap never grows.
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        unsafe { hashmap::hashmap_shrink_to(self.ptr, min_capacity) }
+    }
+
+    /// Insert a key-value pair into the hashmap. The key may be a string, bytes,
+    /// or an integer, as described by [`HashMapKey`]. If the key already
+    /// exists, the old value is returned. If a string key contains an interior
+    /// nul byte, nothing is inserted and the value is dropped; use
+    /// [`HashMap::try_insert`] to detect this.
+    ///
+    /// # Panics
+    /// Panics if the entry for a new key cannot be allocated.
+    ///
+    /// ```
+    /// use bindgen_hashmap::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// assert_eq!(map.insert("foo", 42), None);
+    /// assert_eq!(map.insert("foo", 43), Some(42));
+    /// assert_eq!(map.get("foo"), Some(&43));
+    /// ```
+    pub fn insert(&mut self, key: &(impl HashMapKey + ?Sized), value: V) -> Option<V> {
+        self.insert_boxed(key, Box::new(value))
+            .map(|old_value| *old_value)
+    }
+
+    /// Insert a key-value pair into the hashmap like [`HashMap::insert`], but
+    /// without scanning the key for interior nul bytes first, for keys that
+    /// are known not to contain any.
+    ///
+    /// # Safety
+    /// The key must not contain an interior nul byte, and breaking this is
+    /// undefined behaviour. The C hashmap stores keys with their length and
+    /// would hold such a key just fine, but the `&str` methods rely on no
+    /// string key ever containing a nul byte and reject one without looking it
+    /// up, so they could never find, replace or remove the entry again.
+    pub unsafe fn insert_unchecked(&mut self, key: &str, value: V) -> Option<V> {
+        debug_assert!(
+            !key.contains('\0'),
+            "keys must not contain interior nul bytes"
+        );
+        self.insert_raw(key.as_bytes(), Box::new(value))
+            .expect("failed to allocate hashmap entry")
+            .map(|old_value| *old_value)
+    }
+
+    /// Insert a key-value pair into the hashmap like [`HashMap::insert`], but
+    /// for a value that is already boxed. The box is stored as is, without
+    /// moving the value into a new allocation.
+    pub fn insert_boxed(
+        &mut self,
+        key: &(impl HashMapKey + ?Sized),
+        value: Box<V>,
+    ) -> Option<Box<V>> {
+        match self.try_insert_boxed(key, value) {
+            Err(HashMapError::InvalidKey) => None,
+            result => result.expect("failed to allocate hashmap entry"),
         }
     }
 
-    /// Remove the value associated with the given key from the hashmap. The old
-    /// value is returned.
-    pub fn remove(&mut self, key: &str) -> Option<Box<V>> {
+    /// Insert a key-value pair into the hashmap like [`HashMap::insert`], but
+    /// report whether the key is new or had its value replaced, looking the key
+    /// up only once.
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte or if the
+    /// entry for a new key cannot be allocated.
+    pub fn replace(&mut self, key: &(impl HashMapKey + ?Sized), value: V) -> Replaced<V> {
+        let key = key
+            .key_bytes()
+            .expect("keys must not contain interior nul bytes");
+        let old_value = self
+            .insert_raw(key.as_ref(), Box::new(value))
+            .expect("failed to allocate hashmap entry");
+        match old_value {
+            Some(old_value) => Replaced::Updated(old_value),
+            None => Replaced::Inserted,
+        }
+    }
+
+    /// Insert a key-value pair into the hashmap, failing if the key is a string
+    /// containing an interior nul byte or if the entry for a new key cannot be
+    /// allocated. The value is dropped on failure. If the key already exists,
+    /// the old value is returned.
+    pub fn try_insert(
+        &mut self,
+        key: &(impl HashMapKey + ?Sized),
+        value: V,
+    ) -> Result<Option<V>, HashMapError> {
+        let old_value = self.try_insert_boxed(key, Box::new(value))?;
+        Ok(old_value.map(|old_value| *old_value))
+    }
+
+    /// Insert a boxed value into the hashmap like [`HashMap::try_insert`].
+    fn try_insert_boxed(
+        &mut self,
+        key: &(impl HashMapKey + ?Sized),
+        value: Box<V>,
+    ) -> Result<Option<Box<V>>, HashMapError> {
+        let key = key.key_bytes().ok_or(HashMapError::InvalidKey)?;
+        self.insert_raw(key.as_ref(), value)
+    }
+
+    /// Insert a boxed value for the given byte key, returning the old value if
+    /// the key already exists, and keep the cached length in sync. If the
+    /// entry cannot be allocated, the value is dropped and an error returned.
+    fn insert_raw(&mut self, key: &[u8], value: Box<V>) -> Result<Option<Box<V>>, HashMapError> {
+        let value = Box::into_raw(value);
+        let mut old_value = ptr::null_mut();
         unsafe {
-            let value = hashmap::hashmap_remove(self.ptr, CString::new(key).ok()?.as_ptr());
-            if value.is_null() {
-                None
-            } else {
-                Some(Box::from_raw(value as *mut _))
+            let status = hashmap::hashmap_try_insert(
+                self.ptr,
+                key.as_ptr() as *const c_void,
+                key.len(),
+                value as *mut c_void,
+                &mut old_value,
+            );
+            if status != hashmap::HashMapStatus_HASHMAP_OK {
+                // The C hashmap did not take ownership of the value, so it has
+                // to be dropped here to not leak.
+                drop(Box::from_raw(value));
+                return Err(HashMapError::AllocError);
             }
         }
+        if old_value.is_null() {
+            self.len += 1;
+            Ok(None)
+        } else {
+            Ok(Some(unsafe { Box::from_raw(old_value as *mut V) }))
+        }
     }
-}
 
-impl<V> Default for HashMap<V> {
-    fn default() -> Self {
-        Self {
-            ptr: unsafe { hashmap::hashmap_new() },
-            value: PhantomData,
+    /// Insert a key-value pair into the hashmap only if the key is not present
+    /// yet, looking it up a single time. Returns true if the pair was
+    /// inserted. Otherwise the existing value is left untouched and the given
+    /// value is dropped. String keys containing interior nul bytes are never
+    /// inserted.
+    pub fn insert_if_absent(&mut self, key: &(impl HashMapKey + ?Sized), value: V) -> bool {
+        let Some(key) = key.key_bytes() else {
+            return false;
+        };
+        let key = key.as_ref();
+        let value = Box::into_raw(Box::new(value));
+        let inserted = unsafe {
+            hashmap::hashmap_insert_if_absent(
+                self.ptr,
+                key.as_ptr() as *const c_void,
+                key.len(),
+                value as *mut c_void,
+            )
+        };
+        if inserted {
+            self.len += 1;
+        } else {
+            drop(unsafe { Box::from_raw(value) });
         }
+        inserted
     }
-}
 
-impl<V> Drop for HashMap<V> {
-    fn drop(&mut self) {
-        unsafe { hashmap::hashmap_free(&mut self.ptr as *mut *mut _) }
+    /// Returns true if the hashmap contains a value for the given key. String
+    /// keys containing interior nul bytes can never be stored, so they always
+    /// return false.
+    pub fn contains_key(&self, key: &(impl HashMapKey + ?Sized)) -> bool {
+        let Some(key) = key.key_bytes() else {
+            return false;
+        };
+        let key = key.as_ref();
+        unsafe { hashmap::hashmap_contains(self.ptr, key.as_ptr() as *const c_void, key.len()) }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Returns whether the hashmap contains a value for the given key like
+    /// [`HashMap::contains_key`], but fails if the key is a string containing
+    /// an interior nul byte instead of reporting it as absent.
+    pub fn try_contains_key(&self, key: &(impl HashMapKey + ?Sized)) -> Result<bool, HashMapError> {
+        if key.key_bytes().is_none() {
+            return Err(HashMapError::InvalidKey);
+        }
+        Ok(self.contains_key(key))
+    }
 
-    #[test]
-    fn test_insert() {
-        let mut map = HashMap::new();
-        assert_eq!(map.insert("foo", Box::new("42")), None);
-        assert_eq!(map.insert("foo", Box::new("43")), Some(Box::new("42")));
+    /// Get a reference to the value associated with the given key like
+    /// [`HashMap::get`], but fail if the key is a string containing an interior
+    /// nul byte, so that such a key can be told apart from one that is missing.
+    pub fn try_get(&self, key: &(impl HashMapKey + ?Sized)) -> Result<Option<&V>, HashMapError> {
+        let key = key.key_bytes().ok_or(HashMapError::InvalidKey)?;
+        Ok(unsafe { self.value_ptr(key.as_ref()).map(|value| &*value) })
     }
 
-    #[test]
-    fn test_get() {
-        let mut map = HashMap::new();
-        assert_eq!(map.get("foo"), None);
-        map.insert("foo", Box::new(42));
-        assert_eq!(map.get("foo"), Some(&42));
+    /// Get a reference to the value associated with the given key.
+    pub fn get(&self, key: &(impl HashMapKey + ?Sized)) -> Option<&V> {
+        let key = key.key_bytes()?;
+        unsafe { self.value_ptr(key.as_ref()).map(|value| &*value) }
     }
 
-    #[test]
-    fn test_remove() {
-        let mut map = HashMap::new();
-        assert_eq!(map.remove("foo"), None);
-        map.insert("foo", Box::new(42));
-        assert_eq!(map.remove("foo"), Some(Box::new(42)));
-        assert_eq!(map.get("foo"), None);
-        assert_eq!(map.remove("foo"), None);
+    /// Get the pointer the C hashmap stores for the value associated with the
+    /// given key, without turning it into a reference. It is the pointer
+    /// returned by `Box::<V>::into_raw` when the value was inserted.
+    ///
+    /// The hashmap keeps owning the value, so the pointer must never be freed.
+    /// It stays valid until the value is removed or replaced, or the hashmap is
+    /// cleared or dropped. Reading through it is fine while the hashmap is not
+    /// mutably borrowed, and writing through it is fine while no other
+    /// references to the value exist.
+    pub fn get_raw(&self, key: &(impl HashMapKey + ?Sized)) -> Option<*mut c_void> {
+        let key = key.key_bytes()?;
+        self.value_ptr(key.as_ref())
+            .map(|value| value as *mut c_void)
     }
 
-    #[test]
-    fn test_get_mut_unchecked() {
-        let mut map = HashMap::new();
+    /// Get a clone of the value associated with the given key. Since
+    /// [`HashMap::get`] already only needs a shared borrow, this is a
+    /// shorthand for `get(key).cloned()` for callers that want an owned value.
+    pub fn get_cloned(&self, key: &(impl HashMapKey + ?Sized)) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.get(key).cloned()
+    }
+
+    /// Look up the value pointer for the given byte key. Whether the key is
+    /// present is decided by the entry the C hashmap finds rather than by the
+    /// value pointer being null.
+    fn value_ptr(&self, key: &[u8]) -> Option<*mut V> {
+        self.entry_ptr(key)
+            .map(|entry| unsafe { (*entry).value as *mut V })
+    }
+
+    /// Look up the entry for the given byte key in the C hashmap. Unlike value
+    /// pointers, which are the same for every box of a zero-sized type, entry
+    /// pointers tell entries apart.
+    fn entry_ptr(&self, key: &[u8]) -> Option<*const hashmap::Entry> {
+        let entry = unsafe {
+            hashmap::hashmap_get_entry(self.ptr, key.as_ptr() as *const c_void, key.len())
+        };
+        (!entry.is_null()).then_some(entry)
+    }
+
+    /// Get references to the values associated with each of the given keys,
+    /// in the same order as the keys. Every key is looked up separately, so
+    /// duplicate keys each get their own result.
+    pub fn get_many<'a>(&'a self, keys: &[&(impl HashMapKey + ?Sized)]) -> Vec<Option<&'a V>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    /// Get the stored key and a reference to the value associated with the
+    /// given key. The returned key borrows from the hashmap rather than from
+    /// the query. Returns `None` if the stored key is not valid UTF-8, which
+    /// can only happen for keys given as bytes or integers.
+    pub fn get_key_value(&self, key: &(impl HashMapKey + ?Sized)) -> Option<(&str, &V)> {
+        let key = key.key_bytes()?;
+        let entry = unsafe { &*self.entry_ptr(key.as_ref())? };
+        let stored_key = unsafe {
+            iter::key_str(ptr::slice_from_raw_parts(
+                entry.key as *const u8,
+                entry.key_len,
+            ))?
+        };
+        Some((stored_key, unsafe { &*(entry.value as *const V) }))
+    }
+
+    /// Returns true if any key of the hashmap is associated with a value equal
+    /// to the given one. Unlike key lookups, this compares the values one by
+    /// one until it finds a match, so it takes time linear in the length of
+    /// the hashmap.
+    pub fn contains_value(&self, value: &V) -> bool
+    where
+        V: PartialEq,
+    {
+        // The keys are never looked at, so they don't have to be valid UTF-8.
+        unsafe { RawIter::new(self.ptr) }
+            .any(|(_, stored)| unsafe { &*(stored as *const V) } == value)
+    }
+
+    /// Get some entry of the hashmap without knowing its key, or `None` if it
+    /// is empty. Which entry is returned is arbitrary, though it stays the same
+    /// as long as the hashmap is not modified. Unlike `iter().next()`, this
+    /// does not allocate an iterator, unless the entry found has a key that is
+    /// not valid UTF-8, in which case it falls back to `iter().next()`.
+    pub fn any_entry(&self) -> Option<(&str, &V)> {
         unsafe {
-            assert_eq!(map.get_mut_unchecked("foo"), None);
+            let entry = hashmap::hashmap_any_entry(self.ptr).as_ref()?;
+            match iter::key_str(ptr::slice_from_raw_parts(
+                entry.key as *const u8,
+                entry.key_len,
+            )) {
+                Some(key) => Some((key, &*(entry.value as *const V))),
+                None => self.iter().next(),
+            }
         }
-        map.insert("foo", Box::new(42));
-        unsafe {
-            assert_eq!(map.get_mut_unchecked("foo"), Some(&mut 42));
+    }
+
+    /// Returns the key-value pair with the largest value according to the
+    /// comparison function, or `None` if the hashmap is empty. If several
+    /// values are equally large, which of them is returned is arbitrary. As in
+    /// [`HashMap::iter`], entries whose keys are not valid UTF-8 are skipped.
+    pub fn max_by_value<F: FnMut(&V, &V) -> Ordering>(&self, mut cmp: F) -> Option<(&str, &V)> {
+        self.iter().max_by(|(_, a), (_, b)| cmp(a, b))
+    }
+
+    /// Returns the key-value pair with the smallest value according to the
+    /// comparison function, or `None` if the hashmap is empty, like
+    /// [`HashMap::max_by_value`].
+    pub fn min_by_value<F: FnMut(&V, &V) -> Ordering>(&self, mut cmp: F) -> Option<(&str, &V)> {
+        self.iter().min_by(|(_, a), (_, b)| cmp(a, b))
+    }
+
+    /// Returns up to `n` distinct key-value pairs chosen uniformly at random
+    /// with the given random number generator, in no particular order. If the
+    /// hashmap has at most `n` entries, all of them are returned. The hashmap
+    /// is walked once, keeping a reservoir of `n` entries, and entries whose
+    /// keys are not valid UTF-8 are never chosen, as in [`HashMap::iter`].
+    #[cfg(feature = "rand")]
+    pub fn sample(&self, n: usize, rng: &mut impl rand_core::RngCore) -> Vec<(&str, &V)> {
+        let mut iter = self.iter();
+        let mut sample: Vec<_> = iter.by_ref().take(n).collect();
+        if n == 0 || n >= self.len() {
+            return sample;
+        }
+        for (seen, entry) in (n + 1..).zip(iter) {
+            // Map a random u64 onto 0..seen by taking the high half of the
+            // product, which avoids the division a modulo would need
+            let index = ((u128::from(rng.next_u64()) * seen as u128) >> 64) as usize;
+            if index < n {
+                sample[index] = entry;
+            }
         }
+        sample
+    }
+
+    /// Get a mutable reference to the value associated with the given key.
+    pub fn get_mut(&mut self, key: &(impl HashMapKey + ?Sized)) -> Option<&mut V> {
+        // The exclusive borrow of the hashmap guarantees no other references to
+        // the value exist.
+        unsafe { self.get_mut_unchecked(key) }
+    }
+
+    /// Get mutable references to the values associated with each of the given
+    /// keys at once, in the same order as the keys.
+    ///
+    /// # Panics
+    /// Panics if any two of the keys are equal, or find the same entry like
+    /// keys differing in case do in a case-insensitive hashmap, since their
+    /// references would alias the same value.
+    pub fn get_disjoint_mut<const N: usize>(
+        &mut self,
+        keys: [&(impl HashMapKey + ?Sized); N],
+    ) -> [Option<&mut V>; N] {
+        let keys = keys.map(HashMapKey::key_bytes);
+        let entries = keys
+            .each_ref()
+            .map(|key| self.entry_ptr(key.as_ref()?.as_ref()));
+        for (i, key) in keys.iter().enumerate() {
+            let Some(key) = key else {
+                continue;
+            };
+            assert!(
+                !keys[..i]
+                    .iter()
+                    .flatten()
+                    .any(|other| other.as_ref() == key.as_ref())
+                    && (entries[i].is_none() || !entries[..i].contains(&entries[i])),
+                "duplicate key {:?} passed to get_disjoint_mut",
+                DebugKey(key.as_ref())
+            );
+        }
+        // Each entry owns its own boxed value, so references to values found
+        // in distinct entries never overlap. The exclusive borrow of the
+        // hashmap rules out any other references for as long as they live.
+        entries.map(|entry| entry.map(|entry| unsafe { &mut *((*entry).value as *mut V) }))
+    }
+
+    /// Get mutable references to the values associated with two keys at once,
+    /// with `None` for a key that is missing. Unlike
+    /// [`HashMap::get_disjoint_mut`], the keys may be equal: if both find the
+    /// same entry, its value is returned in the first slot and the second is
+    /// `None`.
+    pub fn get_both_mut(
+        &mut self,
+        a: &(impl HashMapKey + ?Sized),
+        b: &(impl HashMapKey + ?Sized),
+    ) -> (Option<&mut V>, Option<&mut V>) {
+        let (a, mut b) = (
+            a.key_bytes().and_then(|key| self.entry_ptr(key.as_ref())),
+            b.key_bytes().and_then(|key| self.entry_ptr(key.as_ref())),
+        );
+        if a.is_some() && a == b {
+            b = None;
+        }
+        // The values live in distinct entries, each owning its own box, and
+        // the exclusive borrow rules out other references to them.
+        let value = |entry: *const hashmap::Entry| unsafe { &mut *((*entry).value as *mut V) };
+        (a.map(value), b.map(value))
+    }
+
+    /// Get a mutable reference to the value associated with the given key
+    /// through a shared reference to the hashmap. Prefer [`HashMap::get_mut`]
+    /// whenever an exclusive borrow is available.
+    ///
+    /// # Safety
+    /// Since the hashmap does not know if other references to the value exist,
+    /// the safety of this operation is up to the caller. That includes
+    /// references held by other threads when the hashmap is shared between
+    /// them.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut_unchecked(&self, key: &(impl HashMapKey + ?Sized)) -> Option<&mut V> {
+        let key = key.key_bytes()?;
+        self.value_ptr(key.as_ref()).map(|value| &mut *value)
+    }
+
+    /// Get the entry for the given key for in-place manipulation, looking the
+    /// key up only once.
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte.
+    pub fn entry(&mut self, key: &(impl HashMapKey + ?Sized)) -> Entry<'_, V> {
+        Entry::new(self, key)
+    }
+
+    /// Get the entry for the given key like [`HashMap::entry`], but borrow the
+    /// key rather than copying it, so that nothing is allocated unless a value
+    /// is inserted for a vacant key.
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte.
+    pub fn entry_ref<'k, K: HashMapKey + ?Sized>(&mut self, key: &'k K) -> EntryRef<'_, 'k, V, K> {
+        EntryRef::new(self, key)
+    }
+
+    /// Returns a builder for entries whose keys the caller has already hashed
+    /// with [`HashMap::hash_key`], so that they are not hashed again.
+    pub fn raw_entry_mut(&mut self) -> RawEntryBuilderMut<'_, V> {
+        RawEntryBuilderMut::new(self)
+    }
+
+    /// Returns the hash the hashmap computes for the given key, using the
+    /// function passed to [`HashMap::with_hasher`] if there is one and SipHash
+    /// with the secret key of the hashmap otherwise. The hash is only
+    /// meaningful for this hashmap.
+    pub fn hash_key(&self, key: &[u8]) -> u64 {
+        unsafe { hashmap::hashmap_hash(self.ptr, key.as_ptr() as *const c_void, key.len()) }
+    }
+
+    /// Returns the index of the bucket the given key hashes to with the
+    /// current number of buckets, whether or not the key is present. Returns
+    /// `None` if the hashmap has not allocated any buckets yet or the key is a
+    /// string containing an interior nul byte. The index changes whenever the
+    /// hashmap resizes.
+    pub fn bucket_index(&self, key: &(impl HashMapKey + ?Sized)) -> Option<usize> {
+        let key = key.key_bytes()?;
+        let key = key.as_ref();
+        let index = unsafe {
+            hashmap::hashmap_bucket_index(self.ptr, key.as_ptr() as *const c_void, key.len())
+        };
+        (index != usize::MAX).then_some(index)
+    }
+
+    /// Returns a mutable reference to the value associated with the given key,
+    /// first inserting the default if the key is missing. The key is looked up
+    /// only once and the default is dropped if the key is already present.
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte.
+    pub fn get_or_insert(&mut self, key: &(impl HashMapKey + ?Sized), default: V) -> &mut V {
+        self.entry(key).or_insert(default)
+    }
+
+    /// Returns a mutable reference to the value associated with the given key,
+    /// first inserting the result of the function if the key is missing. The
+    /// key is hashed and looked up only once, and the function is only called
+    /// if the key is missing.
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(
+        &mut self,
+        key: &(impl HashMapKey + ?Sized),
+        f: F,
+    ) -> &mut V {
+        self.entry(key).or_insert_with(f)
+    }
+
+    /// Returns a mutable reference to the value associated with the given key
+    /// like [`HashMap::get_or_insert_with`], but with a function that can fail.
+    /// If the key is missing and the function returns an error, nothing is
+    /// inserted and the error is returned.
+    ///
+    /// # Panics
+    /// Panics if the key is a string containing an interior nul byte.
+    pub fn get_or_try_insert_with<F, E>(
+        &mut self,
+        key: &(impl HashMapKey + ?Sized),
+        f: F,
+    ) -> Result<&mut V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        self.entry(key).or_try_insert_with(f)
+    }
+
+    /// Returns an iterator over the key-value pairs of the hashmap. The order of
+    /// iteration is arbitrary and insertion order is not preserved, unless the
+    /// hashmap was created by [`HashMap::ordered`].
+    ///
+    /// Entries whose keys were inserted as bytes and are not valid UTF-8 are
+    /// skipped. Use [`HashMap::iter_bytes`] to see every entry.
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter::new(self)
+    }
+
+    /// Returns an iterator over the key-value pairs of the hashmap with the
+    /// keys as raw bytes, in arbitrary order. Unlike [`HashMap::iter`], it
+    /// yields every entry whether or not its key is valid UTF-8.
+    ///
+    /// ```
+    /// use bindgen_hashmap::HashMap;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert_bytes(&[0xff], 1);
+    /// assert_eq!(map.iter().next(), None);
+    /// assert_eq!(map.iter_bytes().collect::<Vec<_>>(), [(&[0xff][..], &1)]);
+    /// ```
+    pub fn iter_bytes(&self) -> IterBytes<'_, V> {
+        IterBytes::new(self)
+    }
+
+    /// Returns a cursor over the key-value pairs of the hashmap, starting at
+    /// the first one. Its position can be saved to resume from later with
+    /// [`HashMap::cursor_at`].
+    pub fn cursor(&self) -> Cursor<'_, V> {
+        Cursor::new(self, CursorPosition::default())
+    }
+
+    /// Returns a cursor over the key-value pairs of the hashmap that resumes
+    /// from a position saved with [`Cursor::position`]. Unless the hashmap is
+    /// unchanged since the position was saved, some pairs may be skipped or
+    /// yielded again.
+    pub fn cursor_at(&self, position: CursorPosition) -> Cursor<'_, V> {
+        Cursor::new(self, position)
+    }
+
+    /// Returns an iterator over the keys of the hashmap paired with mutable
+    /// references to their values, in arbitrary order. Keys cannot be changed
+    /// in place. Like [`HashMap::iter`], it skips keys that are not valid
+    /// UTF-8.
+    pub fn iter_mut(&mut self) -> IterMut<'_, V> {
+        IterMut::new(self)
+    }
+
+    /// Returns an iterator over the keys of the hashmap in arbitrary order,
+    /// skipping keys that are not valid UTF-8.
+    pub fn keys(&self) -> Keys<'_, V> {
+        Keys::new(self)
+    }
+
+    /// Returns an iterator over the values of the hashmap in arbitrary order.
+    pub fn values(&self) -> Values<'_, V> {
+        Values::new(self)
+    }
+
+    /// Consume the hashmap, returning an iterator over its keys in arbitrary
+    /// order. The values are dropped along the way.
+    pub fn into_keys(self) -> IntoKeys<V> {
+        IntoKeys::new(self)
+    }
+
+    /// Consume the hashmap, returning an iterator over its values in arbitrary
+    /// order.
+    pub fn into_values(self) -> IntoValues<V> {
+        IntoValues::new(self)
+    }
+
+    /// Lock the hashmap into an immutable form that can only be read, for
+    /// sharing between threads. Use [`FrozenHashMap::thaw`] to mutate it again.
+    pub fn freeze(self) -> FrozenHashMap<V> {
+        FrozenHashMap::new(self)
+    }
+
+    /// Returns an iterator over mutable references to the values of the hashmap
+    /// in arbitrary order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, V> {
+        ValuesMut::new(self)
+    }
+
+    /// Remove the value associated with the given key from the hashmap like
+    /// [`HashMap::remove`], but fail if the key is a string containing an
+    /// interior nul byte instead of treating it as missing.
+    pub fn try_remove(
+        &mut self,
+        key: &(impl HashMapKey + ?Sized),
+    ) -> Result<Option<Box<V>>, HashMapError> {
+        let key = key.key_bytes().ok_or(HashMapError::InvalidKey)?;
+        Ok(self.remove_bytes(key.as_ref()))
+    }
+
+    /// Remove the value associated with the given key from the hashmap. The old
+    /// value is returned.
+    pub fn remove(&mut self, key: &(impl HashMapKey + ?Sized)) -> Option<Box<V>> {
+        self.remove_bytes(key.key_bytes()?.as_ref())
+    }
+
+    /// Insert a key-value pair into the hashmap using a key of arbitrary bytes,
+    /// which unlike string keys may contain nul bytes. A string key and a byte
+    /// key with the same bytes refer to the same entry. If the key already
+    /// exists, the old value is returned.
+    ///
+    /// # Panics
+    /// Panics if the entry for a new key cannot be allocated.
+    pub fn insert_bytes(&mut self, key: &[u8], value: V) -> Option<V> {
+        self.insert_raw(key, Box::new(value))
+            .expect("failed to allocate hashmap entry")
+            .map(|old_value| *old_value)
+    }
+
+    /// Get a reference to the value associated with the given byte key.
+    pub fn get_bytes(&self, key: &[u8]) -> Option<&V> {
+        unsafe { self.value_ptr(key).map(|value| &*value) }
+    }
+
+    /// Remove the value associated with the given byte key from the hashmap.
+    /// The old value is returned.
+    pub fn remove_bytes(&mut self, key: &[u8]) -> Option<Box<V>> {
+        unsafe {
+            let value =
+                hashmap::hashmap_remove_bytes(self.ptr, key.as_ptr() as *const c_void, key.len());
+            if value.is_null() {
+                None
+            } else {
+                self.len -= 1;
+                Some(Box::from_raw(value as *mut _))
+            }
+        }
+    }
+
+    /// Remove the entry for the given key from the hashmap, returning the key
+    /// as it was stored along with the old value. A stored key that is not
+    /// valid UTF-8, which only keys inserted as bytes or integers can be, is
+    /// converted lossily.
+    pub fn remove_entry(&mut self, key: &(impl HashMapKey + ?Sized)) -> Option<(String, Box<V>)> {
+        let key = key.key_bytes()?;
+        let key = key.as_ref();
+        let mut stored_key = ptr::null_mut();
+        let mut stored_key_len = 0;
+        unsafe {
+            let value = hashmap::hashmap_remove_entry(
+                self.ptr,
+                key.as_ptr() as *const c_void,
+                key.len(),
+                &mut stored_key,
+                &mut stored_key_len,
+            );
+            if value.is_null() {
+                return None;
+            }
+            self.len -= 1;
+            let owned_key = iter::key_string(ptr::slice_from_raw_parts(
+                stored_key as *const u8,
+                stored_key_len,
+            ));
+            hashmap::hashmap_key_free(stored_key);
+            Some((owned_key, Box::from_raw(value as *mut V)))
+        }
+    }
+
+    /// Copy the hashmap into a [`std::collections::HashMap`], cloning every key
+    /// into a [`String`] and every value. Keys that are not valid UTF-8 are
+    /// converted lossily, so distinct byte keys may collapse into one.
+    #[cfg(feature = "std")]
+    pub fn to_std(&self) -> std::collections::HashMap<String, V>
+    where
+        V: Clone,
+    {
+        self.iter_bytes()
+            .map(|(key, value)| (String::from_utf8_lossy(key).into_owned(), value.clone()))
+            .collect()
+    }
+
+    /// Move every key-value pair of the other hashmap into this one. For keys
+    /// present in both, the resolver is called with the key, the existing value
+    /// and the incoming value, and its result is stored in place of the
+    /// existing value. The entry itself stays, so it keeps its position in an
+    /// ordered hashmap and the case of its key in a case-insensitive one.
+    ///
+    /// Keys that are not valid UTF-8 are merged like any other, though the
+    /// resolver sees them converted lossily. If the resolver panics, the entry
+    /// whose value it was given is removed.
+    ///
+    /// # Panics
+    /// Panics if the entry for a new key cannot be allocated.
+    pub fn merge<F: FnMut(&str, V, V) -> V>(&mut self, other: HashMap<V>, mut resolver: F) {
+        /// Removes the entry whose value was moved out if dropped, which only
+        /// happens when the resolver unwinds.
+        struct Unlink<'a, V> {
+            map: &'a mut HashMap<V>,
+            slot: *mut *mut c_void,
+            hash: u64,
+        }
+
+        impl<V> Drop for Unlink<'_, V> {
+            fn drop(&mut self) {
+                unsafe {
+                    let value = hashmap::hashmap_remove_slot(self.map.ptr, self.slot, self.hash);
+                    // The value was already moved out, only its box is left
+                    drop(Box::from_raw(value as *mut ManuallyDrop<V>));
+                }
+                self.map.len -= 1;
+            }
+        }
+
+        let mut other = other.into_iter();
+        while let Some((key, incoming)) = other.next_bytes() {
+            let mut hash = 0;
+            let slot = unsafe {
+                hashmap::hashmap_get_slot(
+                    self.ptr,
+                    key.as_ptr() as *const c_void,
+                    key.len(),
+                    &mut hash,
+                )
+            };
+            if slot.is_null() {
+                self.insert_raw(key, incoming)
+                    .expect("failed to allocate hashmap entry");
+                continue;
+            }
+
+            let unlink = Unlink {
+                map: self,
+                slot,
+                hash,
+            };
+            let existing = unsafe { ptr::read(*slot as *const V) };
+            let value = resolver(&String::from_utf8_lossy(key), existing, *incoming);
+            mem::forget(unlink);
+            // The box of the existing value is reused for the result
+            unsafe { ptr::write(*slot as *mut V, value) };
+        }
+    }
+
+    /// Remove every key-value pair from the hashmap, returning them as an
+    /// iterator of owned pairs in arbitrary order. Unlike
+    /// [`HashMap::into_iter`], the hashmap stays usable and keeps its buckets.
+    /// Pairs that are not consumed are dropped along with the iterator. Keys
+    /// that are not valid UTF-8 are converted lossily.
+    pub fn drain(&mut self) -> Drain<'_, V> {
+        Drain::new(self)
+    }
+
+    /// Move every key-value pair out of `other` into the hashmap, leaving
+    /// `other` empty but with its buckets. The boxed values are moved as they
+    /// are, and values already stored under the same keys are replaced and
+    /// dropped.
+    ///
+    /// # Panics
+    /// Panics if an entry cannot be allocated, in which case the pairs that
+    /// were not moved yet stay in `other`.
+    pub fn append(&mut self, other: &mut HashMap<V>) {
+        self.reserve(other.len());
+        let mut raw = unsafe { RawIter::new(other.ptr) };
+        while let Some((key, value)) = raw.next() {
+            let mut old_value = ptr::null_mut();
+            unsafe {
+                // The key is freed along with its entry in the other hashmap,
+                // so the value has to be inserted before the entry is removed.
+                let key = &*key;
+                let status = hashmap::hashmap_try_insert(
+                    self.ptr,
+                    key.as_ptr() as *const c_void,
+                    key.len(),
+                    value,
+                    &mut old_value,
+                );
+                assert!(
+                    status == hashmap::HashMapStatus_HASHMAP_OK,
+                    "failed to allocate hashmap entry"
+                );
+                raw.remove(other.ptr);
+                other.len -= 1;
+                if old_value.is_null() {
+                    self.len += 1;
+                } else {
+                    drop(Box::from_raw(old_value as *mut V));
+                }
+            }
+        }
+    }
+
+    /// Returns an iterator that removes and yields the key-value pairs for
+    /// which the predicate returns true, in arbitrary order. Unlike
+    /// [`HashMap::retain`], pairs are removed lazily as the iterator advances,
+    /// so any pairs it has not reached when dropped stay in the hashmap.
+    pub fn extract_if<F: FnMut(&str, &mut V) -> bool>(&mut self, pred: F) -> ExtractIf<'_, V, F> {
+        ExtractIf::new(self, pred)
+    }
+
+    /// Former name of [`HashMap::extract_if`], kept for code written against
+    /// the old unstable `drain_filter` API of the standard library.
+    #[deprecated(since = "0.2.0", note = "renamed to `extract_if`")]
+    pub fn drain_filter<F: FnMut(&str, &mut V) -> bool>(&mut self, pred: F) -> ExtractIf<'_, V, F> {
+        self.extract_if(pred)
+    }
+
+    /// Keep only the key-value pairs for which the predicate returns true,
+    /// removing and dropping the rest in a single pass. The hashmap never
+    /// shrinks as a result, no matter how many pairs are removed. Every pair is
+    /// visited, with keys that are not valid UTF-8 converted lossily.
+    pub fn retain<F: FnMut(&str, &mut V) -> bool>(&mut self, mut f: F) {
+        unsafe {
+            let mut raw = RawIter::new(self.ptr);
+            while let Some((key, value)) = raw.next() {
+                if !f(&String::from_utf8_lossy(&*key), &mut *(value as *mut V)) {
+                    // Update the length before dropping the value, so that
+                    // it stays correct if the value panics while dropped.
+                    self.len -= 1;
+                    drop(Box::from_raw(raw.remove(self.ptr) as *mut V));
+                }
+            }
+        }
+    }
+
+    /// Keep only the key-value pairs whose key satisfies the predicate like
+    /// [`HashMap::retain`], for policies that never need to look at the
+    /// values. The values of removed pairs are still dropped.
+    pub fn retain_keys<F: FnMut(&str) -> bool>(&mut self, mut f: F) {
+        unsafe {
+            let mut raw = RawIter::new(self.ptr);
+            while let Some((key, _)) = raw.next() {
+                if !f(&String::from_utf8_lossy(&*key)) {
+                    self.len -= 1;
+                    drop(Box::from_raw(raw.remove(self.ptr) as *mut V));
+                }
+            }
+        }
+    }
+
+    /// Remove every key-value pair from the hashmap, dropping the values. The
+    /// buckets stay allocated so the hashmap can be refilled without growing.
+    pub fn clear(&mut self) {
+        unsafe { hashmap::hashmap_clear(self.ptr, Some(drop_value::<V>)) }
+        self.len = 0;
+    }
+}
+
+impl<V> Default for HashMap<V> {
+    fn default() -> Self {
+        Self {
+            ptr: unsafe { hashmap::hashmap_new(random_key().as_ptr()) },
+            len: 0,
+            on_resize: None,
+            free_on_drop: true,
+            value: PhantomData,
+        }
+    }
+}
+
+// SAFETY: The C hashmap is owned exclusively by the wrapper, which adopted
+// hashmaps are required to be as well, and made up of plain heap allocations
+// with no thread affinity. The C code has no global or
+// thread-local state, so it can be used from whichever thread owns the map.
+// The resize callback is required to be `Send` as well.
+unsafe impl<V: Send> Send for HashMap<V> {}
+
+// SAFETY: Every method taking `&self` only calls C functions that take a const
+// pointer to the hashmap. None of them mutate it, not even to lazily allocate
+// buckets, so concurrent lookups only ever read shared memory. For the same
+// reason the resize callback, which need not be `Sync`, is never called through
+// a shared reference. The one exception is the probe counter of the
+// `probe-stats` feature, which lookups bump through a cast-away const pointer.
+// They do so with an atomic add, so concurrent lookups don't race on it, and it
+// is only read and reset through `&mut self`.
+unsafe impl<V: Sync> Sync for HashMap<V> {}
+
+impl<V: Clone> Clone for HashMap<V> {
+    /// Create a deep copy of the hashmap with the same number of buckets, where
+    /// every value is cloned into a new box.
+    fn clone(&self) -> Self {
+        let prime_buckets = unsafe { (*self.ptr).prime_buckets };
+        let mut map = match self.bucket_count() {
+            buckets if prime_buckets => Self::with_prime_buckets(buckets),
+            0 => Self::new(),
+            buckets => Self::with_buckets(buckets),
+        };
+        unsafe {
+            hashmap::hashmap_set_hasher(map.ptr, (*self.ptr).hasher);
+            hashmap::hashmap_set_case_insensitive(map.ptr, (*self.ptr).case_insensitive);
+            hashmap::hashmap_set_ordered(map.ptr, (*self.ptr).ordered);
+            hashmap::hashmap_set_inline_keys(map.ptr, (*self.ptr).inline_keys);
+            if !(*self.ptr).arena.is_null() {
+                hashmap::hashmap_use_arena(map.ptr);
+            }
+            if (*self.ptr).open_addressing {
+                hashmap::hashmap_use_open_addressing(map.ptr);
+            }
+            hashmap::hashmap_set_max_load_factor(map.ptr, (*self.ptr).max_load_factor);
+            hashmap::hashmap_set_growth_factor(map.ptr, (*self.ptr).growth_factor);
+        }
+        let mut iter = self.iter();
+        while let Some((key, value)) = iter.next_bytes() {
+            map.insert_bytes(key, value.clone());
+        }
+        map
+    }
+
+    /// Make the hashmap a deep copy of the source like [`Clone::clone`], but
+    /// reuse its buckets when there are already enough of them for every key
+    /// of the source, so that cloning into the same hashmap over and over
+    /// does not reallocate them each time. Hashmaps whose bucket layout
+    /// differs from the source, such as one using open addressing and one
+    /// chaining its entries, are replaced by a fresh clone instead. Either way
+    /// the hashmap keeps its own resize callback registered with
+    /// [`HashMap::on_resize`], since the one of the source cannot be cloned.
+    fn clone_from(&mut self, source: &Self) {
+        let reusable = unsafe {
+            (*self.ptr).prime_buckets == (*source.ptr).prime_buckets
+                && (*self.ptr).open_addressing == (*source.ptr).open_addressing
+                && (*self.ptr).arena.is_null() == (*source.ptr).arena.is_null()
+        };
+        if !reusable {
+            let callback = self.on_resize.take();
+            *self = source.clone();
+            if let Some(callback) = callback {
+                self.set_resize_callback(callback);
+            }
+            return;
+        }
+
+        self.clear();
+        unsafe {
+            hashmap::hashmap_set_hasher(self.ptr, (*source.ptr).hasher);
+            hashmap::hashmap_set_case_insensitive(self.ptr, (*source.ptr).case_insensitive);
+            hashmap::hashmap_set_ordered(self.ptr, (*source.ptr).ordered);
+            hashmap::hashmap_set_inline_keys(self.ptr, (*source.ptr).inline_keys);
+            hashmap::hashmap_set_max_load_factor(self.ptr, (*source.ptr).max_load_factor);
+            hashmap::hashmap_set_growth_factor(self.ptr, (*source.ptr).growth_factor);
+        }
+        self.reserve(source.len());
+        let mut iter = source.iter();
+        while let Some((key, value)) = iter.next_bytes() {
+            self.insert_bytes(key, value.clone());
+        }
+    }
+}
+
+impl<V: PartialEq> PartialEq for HashMap<V> {
+    /// Two hashmaps are equal if they contain the same keys with equal values,
+    /// regardless of the order they were inserted in or how they are laid out
+    /// in their buckets. A case-insensitive hashmap is never equal to one that
+    /// compares keys exactly, since looking up keys in one or the other would
+    /// make equality depend on the order of the operands.
+    fn eq(&self, other: &Self) -> bool {
+        if self.len() != other.len()
+            || unsafe { (*self.ptr).case_insensitive != (*other.ptr).case_insensitive }
+        {
+            return false;
+        }
+
+        let mut iter = self.iter();
+        while let Some((key, value)) = iter.next_bytes() {
+            if other.get_bytes(key) != Some(value) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl<V: Eq> Eq for HashMap<V> {}
+
+/// Hasher for a single entry of a hashmap implementing FNV-1a, used by the
+/// [`Hash`] implementation of [`HashMap`]. It is deterministic so that equal
+/// entries of different hashmaps hash the same.
+struct EntryHasher(u64);
+
+impl EntryHasher {
+    /// Create a hasher with the FNV-1a offset basis.
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for EntryHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+impl<V: Hash> Hash for HashMap<V> {
+    /// Hash the hashmap consistently with its [`PartialEq`] implementation.
+    /// Since equal hashmaps may visit their entries in different orders, every
+    /// entry is hashed on its own and the entry hashes are combined by adding
+    /// them, which does not depend on the order. Keys of case-insensitive
+    /// hashmaps are hashed with their ASCII letters lowercased, and whether the
+    /// hashmap is case-insensitive is hashed as well, since hashmaps only
+    /// compare equal to hashmaps of the same mode.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let case_insensitive = unsafe { (*self.ptr).case_insensitive };
+        let mut sum = 0u64;
+        let mut iter = self.iter();
+        while let Some((key, value)) = iter.next_bytes() {
+            let mut hasher = EntryHasher::new();
+            if case_insensitive {
+                // Same layout as hashing the slice: its length, then its bytes
+                hasher.write_usize(key.len());
+                for byte in key {
+                    hasher.write_u8(byte.to_ascii_lowercase());
+                }
+            } else {
+                key.hash(&mut hasher);
+            }
+            value.hash(&mut hasher);
+            sum = sum.wrapping_add(hasher.finish());
+        }
+        state.write_u8(u8::from(case_insensitive));
+        state.write_usize(self.len());
+        state.write_u64(sum);
+    }
+}
+
+impl<V, K: HashMapKey + ?Sized> Index<&K> for HashMap<V> {
+    type Output = V;
+
+    /// Returns a reference to the value associated with the given key. Use
+    /// [`HashMap::get`] for lookups that may fail.
+    ///
+    /// # Panics
+    /// Panics if the key is not present in the hashmap.
+    fn index(&self, key: &K) -> &V {
+        match key.key_bytes() {
+            Some(bytes) => match self.get(key) {
+                Some(value) => value,
+                None => panic!("key {:?} not found in hashmap", DebugKey(bytes.as_ref())),
+            },
+            None => panic!("key not found in hashmap"),
+        }
+    }
+}
+
+impl<V> FromIterator<(String, V)> for HashMap<V> {
+    /// Collect key-value pairs into a new hashmap, reserving space based on the
+    /// size hint of the iterator. Later values win over earlier ones with the
+    /// same key and keys containing interior nul bytes are skipped.
+    fn from_iter<I: IntoIterator<Item = (String, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V> From<std::collections::HashMap<String, V>> for HashMap<V> {
+    /// Move every key-value pair of a [`std::collections::HashMap`] into a new
+    /// hashmap. Keys containing interior nul bytes are skipped.
+    fn from(map: std::collections::HashMap<String, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<V> Extend<(String, V)> for HashMap<V> {
+    /// Insert every key-value pair from the iterator, boxing the values and
+    /// reserving space based on its size hint first. Existing values with the
+    /// same key are replaced and keys containing interior nul bytes are
+    /// skipped.
+    fn extend<I: IntoIterator<Item = (String, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for (key, value) in iter {
+            self.insert(&key, value);
+        }
+    }
+}
+
+/// Formats a key as a string if it is valid UTF-8 and as an escaped byte string
+/// otherwise.
+struct DebugKey<'a>(&'a [u8]);
+
+impl Debug for DebugKey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match str::from_utf8(self.0) {
+            Ok(key) => key.fmt(f),
+            Err(_) => write!(f, "b\"{}\"", self.0.escape_ascii()),
+        }
+    }
+}
+
+impl<V: Debug> Debug for HashMap<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        let mut iter = self.iter();
+        while let Some((key, value)) = iter.next_bytes() {
+            map.entry(&DebugKey(key), value);
+        }
+        map.finish()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a HashMap<V> {
+    type Item = (&'a str, &'a V);
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a mut HashMap<V> {
+    type Item = (&'a str, &'a mut V);
+    type IntoIter = IterMut<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<V> IntoIterator for HashMap<V> {
+    type Item = (String, Box<V>);
+    type IntoIter = IntoIter<V>;
+
+    /// Consume the hashmap, yielding owned key-value pairs in arbitrary order.
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self)
+    }
+}
+
+impl<V> Drop for HashMap<V> {
+    fn drop(&mut self) {
+        if !self.free_on_drop {
+            // The hashmap outlives the wrapper, so it must not keep calling a
+            // callback that is about to be dropped.
+            if self.on_resize.is_some() {
+                unsafe { hashmap::hashmap_set_resize_callback(self.ptr, None, ptr::null_mut()) };
+            }
+            return;
+        }
+        // The values were allocated as boxes by Rust, so they have to be freed
+        // by Rust as well for their destructors to run.
+        unsafe { hashmap::hashmap_free_with(&mut self.ptr as *mut *mut _, Some(drop_value::<V>)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        ptr,
+        rc::Rc,
+    };
+
+    use super::*;
+
+    /// Value that counts how many times it has been dropped
+    pub(crate) struct Counted(pub(crate) Rc<Cell<usize>>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    /// Hasher giving every key the same hash, so that all keys collide
+    pub(crate) extern "C" fn constant_hasher(_: *const c_char, _: usize) -> u64 {
+        0
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut map = HashMap::new();
+        assert_eq!(map.insert("foo", "42"), None);
+        assert_eq!(map.insert("foo", "43"), Some("42"));
+    }
+
+    #[test]
+    fn test_insert_unchecked() {
+        let mut map = HashMap::new();
+        unsafe {
+            assert_eq!(map.insert_unchecked("foo", 1), None);
+            assert_eq!(map.insert_unchecked("foo", 2), Some(1));
+        }
+        assert_eq!(map.get("foo"), Some(&2));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_insert_boxed() {
+        let mut map = HashMap::new();
+        let value = Box::new(42);
+        let ptr: *const i32 = &*value;
+        assert_eq!(map.insert_boxed("foo", value), None);
+        assert!(std::ptr::eq(map.get("foo").unwrap(), ptr));
+        assert_eq!(map.insert_boxed("foo", Box::new(43)), Some(Box::new(42)));
+    }
+
+    #[test]
+    fn test_get() {
+        let mut map = HashMap::new();
+        assert_eq!(map.get("foo"), None);
+        map.insert("foo", 42);
+        assert_eq!(map.get("foo"), Some(&42));
+    }
+
+    #[test]
+    fn test_get_raw() {
+        let mut map = HashMap::new();
+        assert_eq!(map.get_raw("foo"), None);
+        map.insert("foo", 1);
+        let value = map.get_raw("foo").unwrap();
+        assert!(std::ptr::eq(value as *const i32, map.get("foo").unwrap()));
+        unsafe { *(value as *mut i32) = 2 };
+        assert_eq!(map.get("foo"), Some(&2));
+        assert_eq!(map.get_raw("foo\0"), None);
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut map = HashMap::new();
+        assert_eq!(map.replace("foo", ()), Replaced::Inserted);
+        assert_eq!(map.replace("foo", ()), Replaced::Updated(Box::new(())));
+        assert_eq!(map.replace(&7u32, ()), Replaced::Inserted);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut map = HashMap::new();
+        assert_eq!(map.try_insert("foo", 42), Ok(None));
+        assert_eq!(map.try_insert("foo", 43), Ok(Some(42)));
+        assert_eq!(
+            map.try_insert("foo\0bar", 44),
+            Err(HashMapError::InvalidKey)
+        );
+        assert_eq!(map.insert("foo\0bar", 44), None);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_try_lookups() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        assert_eq!(map.try_get("foo"), Ok(Some(&1)));
+        assert_eq!(map.try_get("bar"), Ok(None));
+        assert_eq!(map.try_get("foo\0"), Err(HashMapError::InvalidKey));
+        assert_eq!(map.try_contains_key("foo"), Ok(true));
+        assert_eq!(map.try_contains_key("bar"), Ok(false));
+        assert_eq!(map.try_contains_key("foo\0"), Err(HashMapError::InvalidKey));
+        assert_eq!(map.try_remove("foo\0"), Err(HashMapError::InvalidKey));
+        assert_eq!(map.try_remove("bar"), Ok(None));
+        assert_eq!(map.try_remove("foo"), Ok(Some(Box::new(1))));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_contains_key() {
+        let mut map = HashMap::new();
+        assert!(!map.contains_key("foo"));
+        map.insert("foo", 42);
+        assert!(map.contains_key("foo"));
+        assert!(!map.contains_key("bar"));
+        assert!(!map.contains_key("foo\0bar"));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut map = HashMap::new();
+        assert_eq!(map.remove("foo"), None);
+        map.insert("foo", 42);
+        assert_eq!(map.remove("foo"), Some(Box::new(42)));
+        assert_eq!(map.get("foo"), None);
+        assert_eq!(map.remove("foo"), None);
+    }
+
+    #[test]
+    fn test_remove_entry() {
+        let mut map = HashMap::new();
+        map.insert("foo", 42);
+        map.insert_bytes(b"f\0o", 43);
+        assert_eq!(
+            map.remove_entry("foo"),
+            Some(("foo".to_owned(), Box::new(42)))
+        );
+        assert_eq!(map.remove_entry("foo"), None);
+        assert_eq!(map.remove_entry("f\0o"), None);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_raw_parts() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        map.insert("bar", 2);
+        assert_eq!(unsafe { hashmap::hashmap_len(map.as_ptr() as *const _) }, 2);
+
+        let ptr = map.into_raw();
+        let mut map = unsafe { HashMap::<i32>::from_raw(ptr) };
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("foo"), Some(&1));
+
+        // A hashmap that is not freed on drop can be adopted again
+        map.set_free_on_drop(false);
+        map.on_resize(|_, _| {});
+        drop(map);
+        let mut map = unsafe { HashMap::<i32>::from_raw(ptr) };
+        assert_eq!(map.get("bar"), Some(&2));
+        map.set_free_on_drop(false);
+        let mut values: Vec<_> = map.into_iter().map(|(_, v)| *v).collect();
+        values.sort();
+        assert_eq!(values, [1, 2]);
+
+        let mut map = unsafe { HashMap::<i32>::from_raw(ptr) };
+        assert!(map.is_empty());
+        map.insert("baz", 3);
+        assert_eq!(map.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_ordered() {
+        let mut map = HashMap::ordered();
+        let keys: Vec<_> = (0..100).map(|i| ((i * 37) % 100).to_string()).collect();
+        for (i, key) in keys.iter().enumerate() {
+            map.insert(key, i);
+        }
+        assert_eq!(map.keys().collect::<Vec<_>>(), keys);
+        assert_eq!(map.any_entry(), Some((&*keys[0], &0)));
+
+        // Replacing a value keeps its place while reinserting moves it last
+        map.insert(&keys[1], 1000);
+        assert_eq!(map.iter().nth(1), Some((&*keys[1], &1000)));
+        map.remove(&keys[0]);
+        map.insert(&keys[0], 0);
+        assert_eq!(map.keys().last(), Some(&*keys[0]));
+        assert_eq!(map.any_entry(), Some((&*keys[1], &1000)));
+
+        map.retain(|_, value| *value % 2 == 0);
+        let expected: Vec<_> = [&keys[1]]
+            .into_iter()
+            .chain(keys[2..].iter().step_by(2))
+            .chain([&keys[0]])
+            .map(String::as_str)
+            .collect();
+        assert_eq!(map.keys().collect::<Vec<_>>(), expected);
+        assert_eq!(map.clone().keys().collect::<Vec<_>>(), expected);
+        let drained: Vec<_> = map.drain().map(|(key, _)| key).collect();
+        assert_eq!(drained, expected);
+
+        map.insert("foo", 1);
+        map.insert("bar", 2);
+        assert_eq!(format!("{map:?}"), r#"{"foo": 1, "bar": 2}"#);
+    }
+
+    #[test]
+    fn test_ordered_drop_order() {
+        /// Value that logs its number when it is dropped
+        struct Logged(i32, Rc<RefCell<Vec<i32>>>);
+
+        impl Drop for Logged {
+            fn drop(&mut self) {
+                self.1.borrow_mut().push(self.0);
+            }
+        }
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut map = HashMap::ordered();
+        for i in 0..50 {
+            map.insert(&i.to_string(), Logged(i, log.clone()));
+        }
+        drop(map.remove("10"));
+        map.insert("10", Logged(10, log.clone()));
+        log.borrow_mut().clear();
+        map.clear();
+        let mut expected: Vec<_> = (0..50).filter(|&i| i != 10).collect();
+        expected.push(10);
+        assert_eq!(*log.borrow(), expected);
+
+        log.borrow_mut().clear();
+        for i in (0..50).rev() {
+            map.insert(&i.to_string(), Logged(i, log.clone()));
+        }
+        drop(map);
+        assert_eq!(*log.borrow(), (0..50).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_arena() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::with_arena();
+        for i in 0..1000 {
+            map.insert(&i.to_string(), Counted(drops.clone()));
+        }
+        assert_eq!(map.len(), 1000);
+
+        // Removing entries drops their values but keeps the arena as it is
+        let usage = map.memory_usage();
+        drop(map.remove("0"));
+        let (key, value) = map.remove_entry("1").unwrap();
+        assert_eq!(key, "1");
+        drop(value);
+        map.retain(|key, _| key.len() > 1);
+        assert_eq!(drops.get(), 10);
+        assert_eq!(map.len(), 990);
+        assert_eq!(map.memory_usage(), usage);
+
+        map.clear();
+        assert_eq!(drops.get(), 1000);
+        for i in 0..10 {
+            map.insert(&i.to_string(), Counted(drops.clone()));
+        }
+        assert!(map.contains_key("9"));
+        drop(map);
+        assert_eq!(drops.get(), 1010);
+    }
+
+    #[test]
+    fn test_get_or_try_insert_with() {
+        let mut map = HashMap::new();
+        map.insert("present", 1);
+        let result = map.get_or_try_insert_with("present", || -> Result<_, ()> { panic!() });
+        assert_eq!(result, Ok(&mut 1));
+
+        assert_eq!(
+            map.get_or_try_insert_with("missing", || Err("failed")),
+            Err("failed")
+        );
+        assert_eq!(map.len(), 1);
+        assert!(!map.contains_key("missing"));
+
+        *map.get_or_try_insert_with("missing", || Ok::<_, ()>(2))
+            .unwrap() += 1;
+        assert_eq!(map.get("missing"), Some(&3));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_validate() {
+        let maps = [
+            HashMap::new(),
+            HashMap::ordered(),
+            HashMap::with_open_addressing(),
+            HashMap::with_prime_buckets(3),
+            HashMap::case_insensitive(),
+            HashMap::with_arena(),
+            HashMap::builder().inline_keys().build(),
+        ];
+        for mut map in maps {
+            assert_eq!(map.validate(), Ok(()));
+            for i in 0..200 {
+                map.insert(&i.to_string(), i);
+                if i % 3 == 0 {
+                    map.remove(&(i / 2).to_string());
+                }
+            }
+            map.retain(|_, v| *v % 5 != 0);
+            assert_eq!(map.validate(), Ok(()));
+            map.shrink_to_fit();
+            assert_eq!(map.validate(), Ok(()));
+
+            unsafe { (*map.ptr).len += 1 };
+            let error = map.validate().unwrap_err();
+            assert!(error.contains("length"), "{error}");
+            unsafe { (*map.ptr).len -= 1 };
+            map.len += 1;
+            assert!(map.validate().unwrap_err().contains("wrapper"));
+            map.len -= 1;
+            map.clear();
+            assert_eq!(map.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_with_small_bucket_counts() {
+        let map = HashMap::<i32>::with_buckets(0);
+        assert_eq!(map.bucket_count(), 0);
+        assert_eq!(map.memory_usage(), HashMap::<i32>::new().memory_usage());
+        assert_eq!(map.get("missing"), None);
+
+        for buckets in [0, 1, 3] {
+            let mut map = HashMap::with_buckets(buckets);
+            assert_eq!(map.remove("missing"), None);
+            for i in 0..100 {
+                assert_eq!(map.insert(&i.to_string(), i), None);
+                assert_eq!(map.validate(), Ok(()));
+            }
+            assert!(map.bucket_count() > buckets);
+            assert!((0..100).all(|i| map.get(&i.to_string()) == Some(&i)));
+            for i in 0..100 {
+                assert_eq!(map.remove(&i.to_string()), Some(Box::new(i)));
+            }
+            assert!(map.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_clone_from() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let mut source = HashMap::new();
+        for i in 0..100 {
+            source.insert(&i.to_string(), i);
+        }
+        let mut map = HashMap::with_buckets(1024);
+        let buckets = map.bucket_count();
+        map.insert("stale", -1);
+        map.clone_from(&source);
+        assert_eq!(map, source);
+        assert_eq!(map.bucket_count(), buckets);
+        source.insert("100", 100);
+        map.clone_from(&source);
+        assert_eq!(map, source);
+        assert_eq!(map.bucket_count(), buckets);
+
+        // Too few buckets are grown and a different layout is replaced
+        let mut small = HashMap::with_buckets(8);
+        small.clone_from(&source);
+        assert_eq!(small, source);
+        let mut open = HashMap::with_open_addressing();
+        open.clone_from(&source);
+        assert_eq!(open, source);
+        assert!(!unsafe { (*open.ptr).open_addressing });
+
+        // The resize callback survives both the reuse and the fallback path
+        let resizes = Arc::new(AtomicUsize::new(0));
+        for mut map in [HashMap::new(), HashMap::with_open_addressing()] {
+            let count = resizes.clone();
+            map.on_resize(move |_, _| {
+                count.fetch_add(1, Ordering::Relaxed);
+            });
+            map.clone_from(&source);
+            resizes.store(0, Ordering::Relaxed);
+            for i in 0..1000 {
+                map.insert(&i.to_string(), i);
+            }
+            assert!(resizes.load(Ordering::Relaxed) > 0);
+        }
+    }
+
+    #[test]
+    fn test_open_addressing() {
+        fn contents(map: &HashMap<u32>) -> std::collections::HashMap<String, u32> {
+            map.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+        }
+
+        let mut map = HashMap::with_open_addressing();
+        let mut expected = std::collections::HashMap::new();
+        let mut state = 1u32;
+        for _ in 0..20_000 {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let key = ((state >> 8) % 500).to_string();
+            match state >> 30 {
+                0 | 1 => assert_eq!(map.insert(&key, state), expected.insert(key, state)),
+                2 => assert_eq!(map.remove(&key).map(|v| *v), expected.remove(&key)),
+                _ => assert_eq!(map.get(&key), expected.get(&key)),
+            }
+            assert_eq!(map.len(), expected.len());
+        }
+        assert_eq!(contents(&map), expected);
+
+        map.retain(|_, value| *value % 3 != 0);
+        expected.retain(|_, value| *value % 3 != 0);
+        assert_eq!(contents(&map), expected);
+        let clone = map.clone();
+        map.shrink_to_fit();
+        assert_eq!(contents(&map), expected);
+        assert_eq!(clone, map);
+        *map.entry("new").or_insert(0) += 1;
+        assert_eq!(
+            map.remove_entry("new"),
+            Some(("new".to_string(), Box::new(1)))
+        );
+        assert_eq!(map.drain().count(), expected.len());
+        assert!(map.is_empty());
+        assert_eq!(map.iter().next(), None);
+    }
+
+    #[test]
+    fn test_open_addressing_wrap_around() {
+        // Every key probes from the last slot, wrapping around to the first
+        extern "C" fn last(_: *const c_char, _: usize) -> u64 {
+            u64::MAX
+        }
+
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::with_hasher(last);
+        unsafe { hashmap::hashmap_use_open_addressing(map.ptr) };
+        for i in 0..5 {
+            map.insert(&i.to_string(), (i, Counted(drops.clone())));
+        }
+        assert_eq!(map.bucket_count(), 8);
+        assert_eq!(map.bucket_stats().max_chain_len, 5);
+
+        map.remove("1");
+        assert_eq!(drops.get(), 1);
+        for i in [0, 2, 3, 4] {
+            assert_eq!(map.get(&i.to_string()).map(|v| v.0), Some(i));
+        }
+        let mut removed: Vec<_> = map
+            .extract_if(|_, (i, _)| *i != 3)
+            .map(|(_, v)| v.0)
+            .collect();
+        removed.sort();
+        assert_eq!(removed, [0, 2, 4]);
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["3"]);
+        assert_eq!(map.cursor().map(|(k, _)| k).collect::<Vec<_>>(), ["3"]);
+        drop(map);
+        assert_eq!(drops.get(), 5);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut map = HashMap::case_insensitive();
+        map.insert("Content-Type", 1);
+        assert_eq!(map.get("content-type"), Some(&1));
+        assert_eq!(map.insert("CONTENT-TYPE", 2), Some(1));
+        assert_eq!(map.len(), 1);
+        assert_eq!(
+            map.get_key_value("content-TYPE"),
+            Some(("Content-Type", &2))
+        );
+        map.insert("straße", 3);
+        assert_eq!(map.get("STRAßE"), Some(&3));
+        assert_eq!(map.get("STRASSE"), None);
+        assert_eq!(map.get("StraẞE"), None);
+
+        let long = "Ab".repeat(300);
+        map.insert(&long, 4);
+        assert_eq!(map.get(&long.to_lowercase()), Some(&4));
+        assert_eq!(map.get(&long[..598]), None);
+        assert_eq!(map.clone().get(&long.to_uppercase()), Some(&4));
+    }
+
+    #[test]
+    fn test_len() {
+        let mut map = HashMap::new();
+        assert_eq!(map.len(), 0);
+        map.insert("foo", 42);
+        map.insert("bar", 43);
+        assert_eq!(map.len(), 2);
+        map.remove("foo");
+        assert_eq!(map.len(), 1);
+        map.remove("foo");
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_len_tracks_every_mutation() {
+        let mut map = HashMap::new();
+        map.insert("a", 1);
+        map.insert("a", 2);
+        assert!(map.insert_if_absent("b", 3));
+        assert!(!map.insert_if_absent("b", 4));
+        *map.entry("c").or_insert(5) += 1;
+        map.insert_bytes(b"d", 6);
+        assert_eq!(map.len(), 4);
+        map.remove_bytes(b"d");
+        map.retain(|_, value| *value != 3);
+        assert_eq!(map.len(), 2);
+        map.merge(
+            HashMap::from_iter([("c".to_string(), 7), ("e".to_string(), 8)]),
+            |_, old, _| old,
+        );
+        assert_eq!(map.len(), 3);
+        map.drain().next();
+        assert_eq!(map.len(), 0);
+        map.insert("f", 9);
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let mut map = HashMap::with_capacity(100);
+        assert_eq!(map.bucket_count(), 256);
+        assert_eq!(map.capacity(), 192);
+        for i in 0..192 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.bucket_count(), 256);
+        map.insert("192", 192);
+        assert_eq!(map.bucket_count(), 512);
+
+        assert_eq!(HashMap::<i32>::with_capacity(6).bucket_count(), 8);
+        assert_eq!(HashMap::<i32>::with_capacity(7).bucket_count(), 16);
+        assert_eq!(HashMap::<i32>::with_capacity(0).bucket_count(), 0);
+        assert_eq!(HashMap::<i32>::new().capacity(), 0);
+    }
+
+    #[test]
+    fn test_remove_never_resizes() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut map = HashMap::with_capacity(1000);
+        map.on_resize(move |old, new| tx.send((old, new)).unwrap());
+        for i in 0..1000 {
+            map.insert(&i.to_string(), i);
+        }
+        for i in 0..1000 {
+            map.remove(&i.to_string());
+            map.insert(&(i + 1000).to_string(), i);
+        }
+        for i in 1000..2000 {
+            map.remove(&i.to_string());
+        }
+        assert!(map.is_empty());
+        assert_eq!(rx.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut map = HashMap::new();
+        map.insert("foo", 0);
+        map.reserve(1000);
+        let buckets = map.bucket_count();
+        assert_eq!(buckets, 2048);
+        for i in 0..1000 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.bucket_count(), buckets);
+        assert_eq!(map.get("foo"), Some(&0));
+
+        map.reserve(0);
+        assert_eq!(map.bucket_count(), buckets);
+    }
+
+    #[test]
+    fn test_reserve_exact() {
+        let mut map = HashMap::new();
+        map.insert("foo", 0);
+        map.reserve_exact(1000);
+        assert_eq!(map.bucket_count(), 1335);
+        assert_eq!(map.capacity(), 1001);
+        for i in 0..1000 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.bucket_count(), 1335);
+        map.insert("bar", 0);
+        assert_eq!(map.bucket_count(), 2670);
+        assert_eq!(map.get("999"), Some(&999));
+
+        map.reserve_exact(0);
+        assert_eq!(map.bucket_count(), 2670);
+        assert_eq!(
+            map.try_reserve_exact(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+
+        let mut map = HashMap::<i32>::with_prime_buckets(0);
+        map.reserve_exact(1000);
+        assert_eq!(map.bucket_count(), 1361);
+    }
+
+    #[test]
+    fn test_max_load_factor() {
+        let mut map = HashMap::with_max_load_factor(0.5);
+        assert_eq!(map.max_load_factor(), 0.5);
+        map.insert("0", 0);
+        assert_eq!(map.capacity(), 4);
+        for i in 1..4 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.bucket_count(), 8);
+        map.insert("4", 4);
+        assert_eq!(map.bucket_count(), 16);
+        assert_eq!(map.capacity(), 8);
+
+        map.reserve(100);
+        assert_eq!(map.bucket_count(), 256);
+
+        let mut map = HashMap::new();
+        for i in 0..6 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.bucket_count(), 8);
+        map.set_max_load_factor(0.25);
+        assert_eq!(map.bucket_count(), 32);
+        assert_eq!(map.clone().max_load_factor(), 0.25);
+        for i in 0..6 {
+            assert_eq!(map.get(&i.to_string()), Some(&i));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "max load factor")]
+    fn test_max_load_factor_invalid() {
+        HashMap::<i32>::new().set_max_load_factor(1.0);
+    }
+
+    #[test]
+    fn test_growth_factor() {
+        let mut map = HashMap::new();
+        assert_eq!(map.growth_factor(), 2.0);
+        map.set_growth_factor(1.5);
+        for i in 0..7 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.bucket_count(), 12);
+        for i in 7..10 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.bucket_count(), 18);
+        assert_eq!(map.clone().growth_factor(), 1.5);
+
+        map.reserve(100);
+        assert_eq!(map.bucket_count(), 256);
+
+        map.set_growth_factor(1.001);
+        for i in 10..1000 {
+            map.insert(&i.to_string(), i);
+        }
+        assert!(map.len() <= map.capacity());
+        for i in 0..1000 {
+            assert_eq!(map.get(&i.to_string()), Some(&i));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "growth factor")]
+    fn test_growth_factor_invalid() {
+        HashMap::<i32>::new().set_growth_factor(1.0);
+    }
+
+    #[test]
+    fn test_prime_buckets() {
+        fn is_prime(n: usize) -> bool {
+            n >= 2
+                && (2..)
+                    .take_while(|d| d * d <= n)
+                    .all(|d| !n.is_multiple_of(d))
+        }
+
+        let mut map = HashMap::with_prime_buckets(100);
+        assert_eq!(map.bucket_count(), 131);
+        for i in 0..1000 {
+            map.insert(&i.to_string(), i);
+            assert!(is_prime(map.bucket_count()));
+        }
+        assert!(map.bucket_count() > 1000);
+        assert_eq!(map.clone().bucket_count(), map.bucket_count());
+
+        for i in 10..1000 {
+            map.remove(&i.to_string());
+        }
+        map.shrink_to_fit();
+        assert_eq!(map.bucket_count(), 17);
+        map.reserve(5000);
+        assert!(is_prime(map.bucket_count()));
+        assert!(map.capacity() >= 5000);
+        for i in 0..10 {
+            assert_eq!(map.get(&i.to_string()), Some(&i));
+        }
+
+        assert_eq!(HashMap::<i32>::with_prime_buckets(0).bucket_count(), 11);
+        assert_eq!(HashMap::<i32>::with_prime_buckets(7).bucket_count(), 11);
+        assert_eq!(HashMap::<i32>::with_prime_buckets(12).bucket_count(), 17);
+    }
+
+    #[test]
+    fn test_try_reserve() {
+        let mut map = HashMap::new();
+        map.insert("foo", 0);
+        assert_eq!(map.try_reserve(100), Ok(()));
+        assert_eq!(map.bucket_count(), 256);
+
+        assert_eq!(
+            map.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            map.try_reserve(usize::MAX / 16),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            map.try_reserve(usize::MAX / 64),
+            Err(TryReserveError::AllocError)
+        );
+        assert_eq!(map.bucket_count(), 256);
+        assert_eq!(map.get("foo"), Some(&0));
+        map.insert("bar", 1);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_try_with_buckets() {
+        let map = HashMap::<i32>::try_with_buckets(100).unwrap();
+        assert_eq!(map.bucket_count(), 100);
+        assert_eq!(
+            HashMap::<i32>::try_with_buckets(usize::MAX).err(),
+            Some(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            HashMap::<i32>::try_with_buckets(usize::MAX / 64).err(),
+            Some(TryReserveError::AllocError)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "buckets: capacity overflow")]
+    fn test_with_buckets_overflow() {
+        HashMap::<i32>::with_buckets(usize::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "failed to allocate")]
+    fn test_with_prime_buckets_overflow() {
+        HashMap::<i32>::with_prime_buckets(usize::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn test_reserve_overflow() {
+        let mut map = HashMap::<i32>::new();
+        map.insert("foo", 0);
+        map.reserve(usize::MAX);
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut map = HashMap::new();
+        map.shrink_to_fit();
+        assert_eq!(map.bucket_count(), 0);
+
+        for i in 0..1000 {
+            map.insert(&i.to_string(), i);
+        }
+        for i in 100..1000 {
+            map.remove(&i.to_string());
+        }
+        map.shrink_to_fit();
+        assert_eq!(map.bucket_count(), 256);
+        for i in 0..100 {
+            assert_eq!(map.get(&i.to_string()), Some(&i));
+        }
+
+        map.clear();
+        map.shrink_to_fit();
+        assert_eq!(map.bucket_count(), 8);
+    }
+
+    #[test]
+    fn test_on_resize() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut map = HashMap::new();
+        map.on_resize(move |old, new| tx.send((old, new)).unwrap());
+        map.reserve(100);
+        for i in 0..1000 {
+            map.insert(&i.to_string(), i);
+        }
+        map.clear();
+        map.shrink_to_fit();
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            [(0, 256), (256, 512), (512, 1024), (1024, 2048), (2048, 8)]
+        );
+
+        // Consuming the hashmap drops the callback as well
+        map.insert("foo", 1);
+        assert_eq!(map.into_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_shrink_to() {
+        let mut map = HashMap::new();
+        for i in 0..1000 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.bucket_count(), 2048);
+        for i in 100..1000 {
+            map.remove(&i.to_string());
+        }
+        map.shrink_to(500);
+        assert_eq!(map.bucket_count(), 1024);
+        assert!(map.capacity() >= 500);
+        map.shrink_to(10);
+        assert_eq!(map.bucket_count(), 256);
+        map.shrink_to(10_000);
+        assert_eq!(map.bucket_count(), 256);
+        for i in 0..100 {
+            assert_eq!(map.get(&i.to_string()), Some(&i));
+        }
+    }
+
+    #[test]
+    fn test_get_mut_unchecked() {
+        let mut map = HashMap::new();
+        unsafe {
+            assert_eq!(map.get_mut_unchecked("foo"), None);
+        }
+        map.insert("foo", 42);
+        unsafe {
+            assert_eq!(map.get_mut_unchecked("foo"), Some(&mut 42));
+        }
+    }
+
+    #[test]
+    fn test_get_key_value() {
+        let mut map = HashMap::new();
+        assert_eq!(map.get_key_value("foo"), None);
+        map.insert("foo", 42);
+
+        let query = String::from("foo");
+        let (key, value) = map.get_key_value(&query).unwrap();
+        drop(query);
+        assert_eq!((key, value), ("foo", &42));
+        assert_eq!(map.get_key_value("foo\0"), None);
+        assert_eq!(map.get_key_value(b"foo"), Some(("foo", &42)));
+        // The little-endian bytes of 255 start with a byte invalid in UTF-8
+        map.insert(&255u32, 7);
+        assert_eq!(map.get_key_value(&255u32), None);
+        map.insert(&7u32, 7);
+        assert_eq!(map.get_key_value(&7u32), Some(("\u{7}\0\0\0", &7)));
+    }
+
+    #[test]
+    fn test_lookups_take_any_key() {
+        let mut map = HashMap::new();
+        assert_eq!(*map.get_or_insert(&1u64, 1), 1);
+        assert_eq!(*map.get_or_insert_with(&2u64, || 2), 2);
+        assert_eq!(
+            map.get_or_try_insert_with(b"\xff", || Ok::<_, ()>(3)),
+            Ok(&mut 3)
+        );
+        assert_eq!(map.get_cloned(&1u64.to_le_bytes()), Some(1));
+        assert_eq!(map[&2u64], 2);
+        assert_eq!(map[b"\xff"], 3);
+        assert_eq!(
+            map.get_many(&[&1u64, &3u64, &2u64]),
+            [Some(&1), None, Some(&2)]
+        );
+        assert_eq!(
+            map.get_both_mut(&1u64, &1u64.to_le_bytes()),
+            (Some(&mut 1), None)
+        );
+        let [one, two, missing] = map.get_disjoint_mut([&1u64, &2u64, &3u64]);
+        mem::swap(one.unwrap(), two.unwrap());
+        assert_eq!(missing, None);
+        assert_eq!(map.get(&1u64), Some(&2));
+        assert_eq!(
+            map.remove_entry(b"\xff"),
+            Some(("\u{fffd}".to_string(), Box::new(3)))
+        );
+        assert_eq!(map.remove_entry("a\0b"), None);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"duplicate key b"\xff""#)]
+    fn test_get_disjoint_mut_duplicate_bytes() {
+        let mut map = HashMap::new();
+        map.insert(b"\xff", 1);
+        map.get_disjoint_mut([b"\xff", b"\xff"]);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut map = HashMap::new();
+        assert_eq!(map.get_mut("foo"), None);
+        map.insert("foo", 42);
+        *map.get_mut("foo").unwrap() += 1;
+        assert_eq!(map.get("foo"), Some(&43));
+    }
+
+    #[test]
+    fn test_clear() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(&i.to_string(), Counted(drops.clone()));
+        }
+        let load_factor = map.load_factor();
+
+        map.clear();
+        assert_eq!(drops.get(), 100);
+        assert!(map.is_empty());
+        assert_eq!(map.get("42").map(|_| ()), None);
+
+        map.insert("foo", Counted(drops.clone()));
+        assert_eq!(map.len(), 1);
+        assert!(map.load_factor() < load_factor);
+    }
+
+    #[test]
+    fn test_retain() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        for i in 0..1000 {
+            map.insert(&i.to_string(), (i, Counted(drops.clone())));
+        }
+        let buckets = map.bucket_count();
+
+        map.retain(|_, (i, _)| *i % 100 == 0);
+        assert_eq!(drops.get(), 990);
+        assert_eq!(map.len(), 10);
+        assert_eq!(map.bucket_count(), buckets);
+        for i in 0..1000 {
+            assert_eq!(map.contains_key(&i.to_string()), i % 100 == 0);
+        }
+
+        map.retain(|key, (i, _)| {
+            *i += 1;
+            key != "0"
+        });
+        assert_eq!(map.len(), 9);
+        assert_eq!(map.get("100").map(|(i, _)| *i), Some(101));
+
+        map.retain(|_, _| false);
+        assert!(map.is_empty());
+        assert_eq!(map.iter().count(), 0);
+        assert_eq!(drops.get(), 1000);
+    }
+
+    #[test]
+    fn test_retain_keys() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(
+                &format!("{}:{i}", ["user", "session"][i % 2]),
+                Counted(drops.clone()),
+            );
+        }
+        map.insert_bytes(b"session:\xff", Counted(drops.clone()));
+
+        map.retain_keys(|key| !key.starts_with("session:"));
+        assert_eq!(drops.get(), 51);
+        assert_eq!(map.len(), 50);
+        assert!(map.keys().all(|key| key.starts_with("user:")));
+    }
+
+    #[test]
+    fn test_with_hasher() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        extern "C" fn constant(_: *const c_char, _: usize) -> u64 {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            0
+        }
+
+        let mut map = HashMap::with_hasher(constant);
+        for i in 0..100 {
+            map.insert(&i.to_string(), i);
+        }
+        assert_eq!(map.remove("42").map(|v| *v), Some(42));
+        for i in 0..100 {
+            assert_eq!(map.get(&i.to_string()).copied(), (i != 42).then_some(i));
+        }
+        assert_eq!(map.len(), 99);
+        let calls = CALLS.load(Ordering::Relaxed);
+        assert!(calls >= 201);
+
+        let clone = map.clone();
+        assert_eq!(clone, map);
+        assert!(CALLS.load(Ordering::Relaxed) >= calls + 99 * 2);
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        use core::mem::size_of;
+
+        let map_size = size_of::<hashmap::HashMap>();
+        let mut map = HashMap::new();
+        assert_eq!(map.memory_usage(), map_size);
+        map.insert("foo", [0u8; 1024]);
+        map.insert_bytes(b"ba\0r", [0; 1024]);
+        let buckets = map.bucket_count() * size_of::<*mut hashmap::Entry>();
+        let entries = 2 * size_of::<hashmap::Entry>() + 4 + 5;
+        assert_eq!(map.memory_usage(), map_size + buckets + entries);
+    }
+
+    #[test]
+    fn test_bucket_stats() {
+        let stats = HashMap::<i32>::new().bucket_stats();
+        assert_eq!(stats.bucket_count, 0);
+        assert_eq!(stats.max_chain_len, 0);
+        assert_eq!(stats.mean_chain_len, 0.0);
+
+        let mut map = HashMap::with_buckets(16);
+        assert_eq!(map.bucket_stats().empty_buckets, 16);
+        for i in 0..10 {
+            map.insert(&i.to_string(), i);
+        }
+        let stats = map.bucket_stats();
+        let occupied = stats.bucket_count - stats.empty_buckets;
+        assert_eq!(stats.bucket_count, 16);
+        assert!(stats.max_chain_len >= 1);
+        assert_eq!(stats.mean_chain_len, 10.0 / occupied as f64);
+
+        let mut map = HashMap::with_hasher(constant_hasher);
+        for i in 0..10 {
+            map.insert(&i.to_string(), i);
+        }
+        let stats = map.bucket_stats();
+        assert_eq!(stats.max_chain_len, 10);
+        assert_eq!(stats.mean_chain_len, 10.0);
+        assert_eq!(stats.empty_buckets, stats.bucket_count - 1);
+    }
+
+    #[test]
+    fn test_bucket_index() {
+        let mut map = HashMap::new();
+        assert_eq!(map.bucket_index("foo"), None);
+        map.insert("foo", 1);
+        let buckets = map.bucket_count();
+        let index = map.bucket_index("foo").unwrap();
+        assert_eq!(index as u64, map.hash_key(b"foo") % buckets as u64);
+        assert!(map.bucket_index(b"bar").unwrap() < buckets);
+        assert_eq!(map.bucket_index("foo\0"), None);
+
+        let mut map = HashMap::case_insensitive();
+        map.insert("foo", 1);
+        assert_eq!(map.bucket_index("FOO"), map.bucket_index("foo"));
+    }
+
+    #[test]
+    fn test_get_or_insert() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        map.get_or_insert("foo", Counted(drops.clone()));
+        assert_eq!(drops.get(), 0);
+        map.get_or_insert("foo", Counted(drops.clone()));
+        assert_eq!(drops.get(), 1);
+        assert_eq!(map.len(), 1);
+
+        let mut map = HashMap::new();
+        *map.get_or_insert("foo", 1) += 1;
+        assert_eq!(*map.get_or_insert("foo", 10), 2);
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut map = HashMap::new();
+        *map.get_or_insert_with("foo", || 1) += 1;
+        assert_eq!(map.get("foo"), Some(&2));
+        assert_eq!(
+            *map.get_or_insert_with("foo", || panic!("key is present")),
+            2
+        );
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn test_get_or_insert_with_resize() {
+        let mut map = HashMap::new_eager();
+        for i in 0..map.capacity() {
+            map.insert(&i.to_string(), vec![i]);
+        }
+        let buckets = map.bucket_count();
+        let value = map.get_or_insert_with("foo", Vec::new);
+        value.push(1);
+        assert_eq!(map.bucket_count(), buckets * 2);
+        assert_eq!(map.get("foo"), Some(&vec![1]));
+
+        for i in map.len()..map.capacity() {
+            map.insert(&i.to_string(), vec![i]);
+        }
+        let value = map.entry("bar").or_insert_with(Vec::new);
+        value.push(2);
+        assert_eq!(map.bucket_count(), buckets * 4);
+        assert_eq!(map.get("bar"), Some(&vec![2]));
+    }
+
+    #[test]
+    fn test_index() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        map.insert("bar", 2);
+        assert_eq!(map["foo"], 1);
+        assert_eq!(map["bar"], 2);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"key "baz" not found"#)]
+    fn test_index_missing() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        let _ = map["baz"];
+    }
+
+    #[test]
+    fn test_merge() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        let mut other = HashMap::new();
+        for i in 0..10 {
+            map.insert(&i.to_string(), (i, Counted(drops.clone())));
+            other.insert(&(i + 5).to_string(), (20 - i, Counted(drops.clone())));
+        }
+
+        map.merge(other, |_, existing, incoming| {
+            if existing.0 >= incoming.0 {
+                existing
+            } else {
+                incoming
+            }
+        });
+        assert_eq!(drops.get(), 5);
+        assert_eq!(map.len(), 15);
+        for i in 0..15 {
+            let expected = if i < 5 { i } else { 25 - i };
+            assert_eq!(map.get(&i.to_string()).map(|(v, _)| *v), Some(expected));
+        }
+        drop(map);
+        assert_eq!(drops.get(), 20);
+    }
+
+    #[test]
+    fn test_merge_keeps_entry() {
+        let mut map = HashMap::ordered();
+        for key in ["a", "b", "c"] {
+            map.insert(key, 1);
+        }
+        map.merge([("a".to_string(), 2)].into_iter().collect(), |_, a, b| {
+            a + b
+        });
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(map.get("a"), Some(&3));
+
+        let mut map = HashMap::case_insensitive();
+        map.insert("Foo", 1);
+        let mut other = HashMap::case_insensitive();
+        other.insert("foo", 2);
+        map.merge(other, |key, a, b| {
+            assert_eq!(key, "foo");
+            a + b
+        });
+        assert_eq!(map.keys().collect::<Vec<_>>(), ["Foo"]);
+        assert_eq!(map.get("FOO"), Some(&3));
+    }
+
+    #[test]
+    fn test_merge_resolver_panic() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        map.insert("a", Counted(drops.clone()));
+        map.insert("b", Counted(drops.clone()));
+        let mut other = HashMap::new();
+        other.insert("a", Counted(drops.clone()));
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            map.merge(other, |_, _, _| panic!("resolver failed"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 2);
+        assert_eq!(map.len(), 1);
+        assert!(!map.contains_key("a"));
+        assert_eq!(map.validate(), Ok(()));
+        drop(map);
+        assert_eq!(drops.get(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_std_conversion() {
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(&i.to_string(), i);
+        }
+
+        let std_map = map.to_std();
+        assert_eq!(std_map.len(), map.len());
+        for i in 0..100 {
+            assert_eq!(std_map.get(&i.to_string()), Some(&i));
+        }
+
+        let converted = HashMap::from(std_map);
+        assert_eq!(converted.len(), map.len());
+        assert_eq!(converted, map);
+    }
+
+    #[test]
+    fn test_load_factor_empty() {
+        let map = HashMap::<i32>::new();
+        assert_eq!(map.bucket_count(), 0);
+        assert!(map.load_factor().is_finite());
+        assert_eq!(map.load_factor(), 0.0);
+
+        let mut map = HashMap::new_eager();
+        assert_eq!(map.bucket_count(), 8);
+        assert_eq!(map.load_factor(), 0.0);
+        map.insert("foo", 0);
+        map.insert("bar", 1);
+        assert_eq!(map.bucket_count(), 8);
+        assert_eq!(map.load_factor(), 0.25);
+    }
+
+    #[test]
+    fn test_insert_if_absent() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        assert!(map.insert_if_absent("foo", (1, Counted(drops.clone()))));
+        assert!(!map.insert_if_absent("foo", (2, Counted(drops.clone()))));
+        assert_eq!(drops.get(), 1);
+        assert_eq!(map.get("foo").map(|(v, _)| *v), Some(1));
+        assert!(!map.insert_if_absent("b\0r", (3, Counted(drops.clone()))));
+        assert_eq!(drops.get(), 2);
+        assert_eq!(map.len(), 1);
+        assert!(map.insert_if_absent(&7u64, (4, Counted(drops.clone()))));
+        assert!(!map.insert_if_absent(&7u64.to_le_bytes(), (5, Counted(drops.clone()))));
+        assert_eq!(drops.get(), 3);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn test_contains_value() {
+        let mut map = HashMap::new();
+        assert!(!map.contains_value(&1));
+        map.insert("foo", 1);
+        map.insert_bytes(b"\xff", 2);
+        assert!(map.contains_value(&1));
+        assert!(map.contains_value(&2));
+        assert!(!map.contains_value(&3));
+    }
+
+    #[test]
+    fn test_any_entry() {
+        let mut map = HashMap::new();
+        assert_eq!(map.any_entry(), None);
+        map.insert("foo", 1);
+        assert_eq!(map.any_entry(), Some(("foo", &1)));
+        map.insert("bar", 2);
+        let (key, value) = map.any_entry().unwrap();
+        assert_eq!(map.get(key), Some(value));
+        map.clear();
+        assert_eq!(map.any_entry(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn test_sample() {
+        /// Xorshift generator, good enough to spread samples in a test
+        struct XorShift(u64);
+
+        impl rand_core::RngCore for XorShift {
+            fn next_u32(&mut self) -> u32 {
+                self.next_u64() as u32
+            }
+
+            fn next_u64(&mut self) -> u64 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0
+            }
+
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                dest.fill_with(|| self.next_u32() as u8);
+            }
+
+            fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+                self.fill_bytes(dest);
+                Ok(())
+            }
+        }
+
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        assert!(HashMap::<i32>::new().sample(3, &mut rng).is_empty());
+
+        let mut map = HashMap::new();
+        for i in 0..10 {
+            map.insert(&i.to_string(), i);
+        }
+        assert!(map.sample(0, &mut rng).is_empty());
+        for n in [10, 20, usize::MAX] {
+            let mut all: Vec<_> = map
+                .sample(n, &mut rng)
+                .into_iter()
+                .map(|(_, v)| *v)
+                .collect();
+            all.sort();
+            assert_eq!(all, (0..10).collect::<Vec<_>>());
+        }
+
+        // Every entry should be picked about 3,000 times out of 10,000 samples
+        let mut counts = [0; 10];
+        for _ in 0..10_000 {
+            let sample = map.sample(3, &mut rng);
+            assert_eq!(sample.len(), 3);
+            for (key, value) in &sample {
+                assert_eq!(map.get(*key), Some(*value));
+                counts[**value as usize] += 1;
+            }
+            let mut values: Vec<_> = sample.iter().map(|(_, v)| **v).collect();
+            values.sort();
+            values.dedup();
+            assert_eq!(values.len(), 3);
+        }
+        assert!(counts.iter().all(|&count| (2_700..3_300).contains(&count)));
+    }
+
+    #[test]
+    fn test_min_max_by_value() {
+        let mut map = HashMap::new();
+        assert_eq!(map.max_by_value(i32::cmp), None);
+        assert_eq!(map.min_by_value(i32::cmp), None);
+        for (key, value) in [("a", 3), ("b", -1), ("c", 7), ("d", -1)] {
+            map.insert(key, value);
+        }
+        assert_eq!(map.max_by_value(i32::cmp), Some(("c", &7)));
+        assert_eq!(map.min_by_value(|a, b| b.cmp(a)), Some(("c", &7)));
+        let (key, value) = map.min_by_value(i32::cmp).unwrap();
+        assert!(key == "b" || key == "d");
+        assert_eq!(*value, -1);
+    }
+
+    #[test]
+    fn test_get_cloned() {
+        let mut map = HashMap::new();
+        map.insert("foo", vec![1, 2]);
+        let shared = &map;
+        assert_eq!(shared.get_cloned("foo"), Some(vec![1, 2]));
+        assert_eq!(shared.get_cloned("bar"), None);
+    }
+
+    #[test]
+    fn test_get_many() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        map.insert("bar", 2);
+        assert_eq!(
+            map.get_many(&["bar", "baz", "foo", "bar"]),
+            [Some(&2), None, Some(&1), Some(&2)]
+        );
+        assert!(map.get_many(&[] as &[&str]).is_empty());
+    }
+
+    #[test]
+    fn test_get_disjoint_mut() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        map.insert("bar", 2);
+        if let [Some(foo), None, Some(bar)] = map.get_disjoint_mut(["foo", "baz", "bar"]) {
+            std::mem::swap(foo, bar);
+        } else {
+            panic!("expected foo and bar to be found");
+        }
+        assert_eq!(map.get("foo"), Some(&2));
+        assert_eq!(map.get("bar"), Some(&1));
+        assert_eq!(map.get_disjoint_mut([] as [&str; 0]), []);
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate key")]
+    fn test_get_disjoint_mut_case_insensitive() {
+        let mut map = HashMap::case_insensitive();
+        map.insert("foo", 1);
+        map.get_disjoint_mut(["foo", "FOO"]);
+    }
+
+    #[test]
+    #[cfg(feature = "probe-stats")]
+    fn test_take_probe_count() {
+        // Every key lands in the same bucket, appended to its chain
+        let mut map = HashMap::with_hasher(constant_hasher);
+        for key in ["a", "b", "c"] {
+            map.insert(key, ());
+        }
+        assert_eq!(map.take_probe_count(), 1 + 2);
+        assert_eq!(map.take_probe_count(), 0);
+        map.get("a");
+        assert_eq!(map.take_probe_count(), 1);
+        map.get("c");
+        map.get("d");
+        assert_eq!(map.take_probe_count(), 3 + 3);
+    }
+
+    #[test]
+    fn test_get_both_mut() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        map.insert("bar", 2);
+        if let (Some(foo), Some(bar)) = map.get_both_mut("foo", "bar") {
+            std::mem::swap(foo, bar);
+        } else {
+            panic!("expected foo and bar to be found");
+        }
+        assert_eq!(map.get("foo"), Some(&2));
+        assert_eq!(map.get_both_mut("foo", "baz"), (Some(&mut 2), None));
+        assert_eq!(map.get_both_mut("baz", "bar"), (None, Some(&mut 1)));
+        assert_eq!(map.get_both_mut("foo", "foo"), (Some(&mut 2), None));
+        assert_eq!(map.get_both_mut("baz", "baz"), (None, None));
+        assert_eq!(map.get_both_mut("foo\0", "bar"), (None, Some(&mut 1)));
+
+        let mut map = HashMap::case_insensitive();
+        map.insert("foo", 1);
+        assert_eq!(map.get_both_mut("foo", "FOO"), (Some(&mut 1), None));
+    }
+
+    #[test]
+    fn test_get_disjoint_mut_zero_sized() {
+        // Every box of a zero-sized value has the same pointer
+        let mut map = HashMap::new();
+        map.insert("a", ());
+        map.insert("b", ());
+        assert_eq!(
+            map.get_disjoint_mut(["a", "b"]),
+            [Some(&mut ()), Some(&mut ())]
+        );
+        assert_eq!(map.get_both_mut("a", "b"), (Some(&mut ()), Some(&mut ())));
+        assert_eq!(map.get_both_mut("a", "a"), (Some(&mut ()), None));
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate key")]
+    fn test_get_disjoint_mut_duplicate() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        map.get_disjoint_mut(["foo", "bar", "foo"]);
+    }
+
+    #[test]
+    fn test_zero_sized_values() {
+        let mut map = HashMap::new();
+        assert_eq!(map.insert("foo", ()), None);
+        assert_eq!(map.insert("foo", ()), Some(()));
+        assert_eq!(map.get("foo"), Some(&()));
+        assert!(map.contains_key("foo"));
+        assert_eq!(map.get("bar"), None);
+        assert_eq!(map.remove("foo").map(|v| *v), Some(()));
+        assert_eq!(map.get("foo"), None);
+        assert!(map.is_empty());
+
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Marker;
+        impl Drop for Marker {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut map = HashMap::new();
+        for i in 0..10 {
+            map.insert(&i.to_string(), Marker);
+        }
+        assert_eq!(map.values().count(), 10);
+        drop(map.remove("0"));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+        drop(map);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 10);
+    }
+
+    #[test]
+    fn test_contains_null_value() {
+        unsafe {
+            let mut map = hashmap::hashmap_new(ptr::null());
+            let key = b"foo";
+            let key_ptr = key.as_ptr() as *const c_void;
+            hashmap::hashmap_insert_bytes(map, key_ptr, key.len(), ptr::null_mut());
+            assert!(hashmap::hashmap_get_bytes(map, key_ptr, key.len()).is_null());
+            assert!(hashmap::hashmap_contains(map, key_ptr, key.len()));
+            assert!(!hashmap::hashmap_contains(
+                map,
+                b"bar".as_ptr() as *const c_void,
+                3
+            ));
+            hashmap::hashmap_free_with(&mut map, None);
+        }
+    }
+
+    /// Global allocator that fails every allocation on a thread while it runs
+    /// [`fail_allocations`]. Without `std`, the C hashmap allocates through it
+    /// as well, so it can simulate the C side running out of memory. With
+    /// `std` it only covers allocations made by Rust.
+    mod failing_alloc {
+        use std::{
+            alloc::{GlobalAlloc, Layout, System},
+            cell::Cell,
+            ptr,
+        };
+
+        std::thread_local! {
+            /// Whether allocations on this thread should fail
+            static FAIL: Cell<bool> = const { Cell::new(false) };
+        }
+
+        /// Allocator wrapping the system allocator
+        struct FailingAlloc;
+
+        unsafe impl GlobalAlloc for FailingAlloc {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                if FAIL.with(Cell::get) {
+                    ptr::null_mut()
+                } else {
+                    System.alloc(layout)
+                }
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                System.dealloc(ptr, layout)
+            }
+        }
+
+        #[global_allocator]
+        static ALLOC: FailingAlloc = FailingAlloc;
+
+        /// Run the function with every allocation on this thread failing.
+        pub(super) fn fail_allocations<T>(f: impl FnOnce() -> T) -> T {
+            FAIL.with(|fail| fail.set(true));
+            let result = f();
+            FAIL.with(|fail| fail.set(false));
+            result
+        }
+    }
+
+    #[test]
+    fn test_entry_ref_does_not_allocate() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        failing_alloc::fail_allocations(|| {
+            *map.entry_ref("foo").or_insert(0) += 1;
+            map.entry_ref("foo").and_modify(|v| *v += 1);
+        });
+        assert_eq!(map.get("foo"), Some(&3));
+    }
+
+    /// Only the C hashmap of a `no_std` build allocates through
+    /// [`failing_alloc`], so this needs `--no-default-features`, which
+    /// `make test` passes in its second run.
+    #[test]
+    #[cfg(not(feature = "std"))]
+    fn test_insert_alloc_failure() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        /// Number of markers dropped so far
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        /// Zero-sized value whose box never allocates
+        struct Marker;
+
+        impl Drop for Marker {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut map = HashMap::with_buckets(8);
+        map.insert("foo", Marker);
+        let result = failing_alloc::fail_allocations(|| map.try_insert("bar", Marker));
+        assert!(matches!(result, Err(HashMapError::AllocError)));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+        assert_eq!(map.len(), 1);
+        assert!(!map.contains_key("bar"));
+
+        // Replacing a value needs no allocation
+        let result = failing_alloc::fail_allocations(|| map.try_insert("foo", Marker));
+        assert!(matches!(result, Ok(Some(_))));
+        drop(result);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_panicking_drop_aborts() {
+        /// Value that panics when dropped
+        struct PanicOnDrop;
+
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                panic!("value panicked while dropped");
+            }
+        }
+
+        // The panic aborts, so it has to happen in a child process running
+        // only this test.
+        if std::env::var_os("HASHMAP_PANIC_ON_DROP").is_some() {
+            let mut map = HashMap::new();
+            map.insert("foo", PanicOnDrop);
+            map.clear();
+            return;
+        }
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "tests::test_panicking_drop_aborts",
+                "--nocapture",
+            ])
+            .env("HASHMAP_PANIC_ON_DROP", "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            // SIGABRT
+            assert_eq!(output.status.signal(), Some(6));
+        }
+    }
+
+    #[test]
+    fn test_panicking_drop_in_retain() {
+        /// Value that panics when dropped unless it is defused first
+        struct PanicOnDrop(bool);
+
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                if self.0 {
+                    panic!("value panicked while dropped");
+                }
+            }
+        }
+
+        let mut map = HashMap::new();
+        map.insert("foo", PanicOnDrop(true));
+        map.insert("bar", PanicOnDrop(false));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            map.retain(|key, _| key != "foo");
+        }));
+        assert!(result.is_err());
+        assert_eq!(map.len(), 1);
+        assert!(map.get("foo").is_none());
+    }
+
+    #[test]
+    fn test_send() {
+        let mut map = HashMap::new();
+        map.insert("foo", String::from("bar"));
+        let map = std::thread::spawn(move || {
+            map.insert("baz", String::from("qux"));
+            map
+        })
+        .join()
+        .unwrap();
+        assert_eq!(map.get("foo").map(String::as_str), Some("bar"));
+        assert_eq!(map.get("baz").map(String::as_str), Some("qux"));
+    }
+
+    #[test]
+    fn test_sync() {
+        let mut map = HashMap::new();
+        for i in 0..1000 {
+            map.insert(&i.to_string(), i);
+        }
+        let map = std::sync::Arc::new(map);
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let map = map.clone();
+                std::thread::spawn(move || {
+                    for i in (t..1000).step_by(4) {
+                        assert_eq!(map.get(&i.to_string()), Some(&i));
+                    }
+                    assert!(!map.contains_key("1000"));
+                    assert_eq!(map.iter().count(), 1000);
+                    assert_eq!(map.values().sum::<i32>(), 499_500);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_drop() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(&i.to_string(), Counted(drops.clone()));
+        }
+        map.insert("0", Counted(drops.clone()));
+        assert_eq!(drops.get(), 1);
+
+        drop(map);
+        assert_eq!(drops.get(), 101);
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut map = HashMap::new();
+        assert_eq!(format!("{map:?}"), "{}");
+        map.insert("foo", 42);
+        assert_eq!(format!("{map:?}"), r#"{"foo": 42}"#);
+        map.insert("bar", 43);
+        let debug = format!("{map:?}");
+        assert!(debug == r#"{"foo": 42, "bar": 43}"# || debug == r#"{"bar": 43, "foo": 42}"#);
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut map = HashMap::new();
+        assert_eq!(map.insert_bytes(b"foo\0bar", 1), None);
+        assert_eq!(map.insert_bytes(b"foo", 2), None);
+        assert_eq!(map.insert_bytes(b"\xff", 3), None);
+        assert_eq!(map.len(), 3);
+
+        assert_eq!(map.get_bytes(b"foo\0bar"), Some(&1));
+        assert_eq!(map.get_bytes(b"foo"), Some(&2));
+        assert_eq!(map.get("foo"), Some(&2));
+        assert_eq!(map.get_bytes(b"foo\0"), None);
+
+        assert_eq!(map.remove_bytes(b"foo\0bar"), Some(Box::new(1)));
+        assert_eq!(map.get_bytes(b"foo\0bar"), None);
+        assert_eq!(map.get("foo"), Some(&2));
+        assert!(format!("{map:?}").contains(r#"b"\xff": 3"#));
+    }
+
+    #[test]
+    fn test_clone() {
+        /// Value that counts how many times it has been cloned
+        struct Cloned(Rc<Cell<usize>>);
+
+        impl Clone for Cloned {
+            fn clone(&self) -> Self {
+                self.0.set(self.0.get() + 1);
+                Self(self.0.clone())
+            }
+        }
+
+        let clones = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        for i in 0..100 {
+            map.insert(&i.to_string(), Cloned(clones.clone()));
+        }
+        map.insert_bytes(b"foo\0bar", Cloned(clones.clone()));
+
+        let copy = map.clone();
+        assert_eq!(clones.get(), map.len());
+        assert_eq!(copy.len(), map.len());
+        assert_eq!(copy.bucket_count(), map.bucket_count());
+        assert!(copy.get_bytes(b"foo\0bar").is_some());
+
+        drop(map);
+        assert!(copy.get("42").is_some());
+
+        for map in [
+            HashMap::<i32>::with_buckets(1),
+            HashMap::with_buckets(3),
+            HashMap::with_prime_buckets(5),
+        ] {
+            assert_eq!(map.clone().bucket_count(), map.bucket_count());
+        }
+        let mut map = HashMap::with_buckets(3);
+        map.insert("foo", 1);
+        map.insert("bar", 2);
+        assert_eq!(map.clone().bucket_count(), map.bucket_count());
+    }
+
+    #[test]
+    fn test_eq_ignores_buckets() {
+        assert_eq!(HashMap::<i32>::new(), HashMap::with_buckets(64));
+        assert_eq!(HashMap::<i32>::default(), HashMap::new_eager());
+        assert_eq!(
+            HashMap::<i32>::with_prime_buckets(7),
+            HashMap::with_capacity(1000)
+        );
+
+        let mut small = HashMap::new();
+        let mut large = HashMap::with_buckets(1024);
+        small.insert("foo", 1);
+        large.insert("foo", 1);
+        assert_eq!(small, large);
+        large.remove("foo");
+        large.shrink_to_fit();
+        assert_ne!(small, large);
+    }
+
+    #[test]
+    fn test_eq() {
+        let forward: HashMap<i32> = (0..100).map(|i| (i.to_string(), i)).collect();
+        let mut backward: HashMap<i32> = (0..100).rev().map(|i| (i.to_string(), i)).collect();
+        assert_eq!(forward, backward);
+
+        backward.insert("42", 0);
+        assert_ne!(forward, backward);
+        backward.insert("42", 42);
+        assert_eq!(forward, backward);
+
+        backward.remove("42");
+        assert_ne!(forward, backward);
+        backward.insert("foo", 42);
+        assert_ne!(forward, backward);
+    }
+
+    #[test]
+    fn test_eq_case_insensitive() {
+        let mut ci = HashMap::case_insensitive();
+        let mut cs = HashMap::new();
+        ci.insert("A", 1);
+        cs.insert("a", 1);
+        assert_ne!(ci, cs);
+        assert_ne!(cs, ci);
+        let mut upper = HashMap::new();
+        upper.insert("A", 1);
+        assert_ne!(ci, upper);
+        assert_ne!(upper, ci);
+
+        let mut other = HashMap::case_insensitive();
+        other.insert("a", 1);
+        assert_eq!(ci, other);
+        assert_eq!(other, ci);
+    }
+
+    #[test]
+    fn test_hash() {
+        use std::{collections::HashSet, hash::BuildHasher};
+
+        let state = std::collections::hash_map::RandomState::new();
+        let mut small = HashMap::with_buckets(8);
+        let mut large = HashMap::with_buckets(1024);
+        for i in 0..100 {
+            small.insert(&i.to_string(), i);
+            large.insert(&(99 - i).to_string(), 99 - i);
+        }
+        assert_eq!(state.hash_one(&small), state.hash_one(&large));
+        large.insert("42", 0);
+        assert_ne!(state.hash_one(&small), state.hash_one(&large));
+
+        let mut lower = HashMap::case_insensitive();
+        lower.insert("content-type", 1);
+        let mut upper = HashMap::case_insensitive();
+        upper.insert("Content-Type", 1);
+        assert_eq!(lower, upper);
+        assert_eq!(state.hash_one(&lower), state.hash_one(&upper));
+
+        let set: HashSet<_> = [small, large, lower.clone(), upper].into_iter().collect();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&lower));
+    }
+
+    #[test]
+    fn test_hash_case_insensitive_modes() {
+        use std::hash::BuildHasher;
+
+        let state = std::collections::hash_map::RandomState::new();
+        for key in ["a", "A"] {
+            let mut ci = HashMap::case_insensitive();
+            let mut cs = HashMap::new();
+            ci.insert(key, 1);
+            cs.insert(key, 1);
+            assert_ne!(ci, cs);
+            assert_ne!(state.hash_one(&ci), state.hash_one(&cs));
+            // Equal hashmaps of either mode still hash the same
+            assert_eq!(state.hash_one(&cs), state.hash_one(cs.clone()));
+            let mut other = HashMap::case_insensitive();
+            other.insert(&key.to_ascii_lowercase(), 1);
+            assert_eq!(ci, other);
+            assert_eq!(state.hash_one(&ci), state.hash_one(&other));
+        }
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let pairs = vec![
+            ("foo".to_string(), 1),
+            ("bar".to_string(), 2),
+            ("foo".to_string(), 3),
+        ];
+        let map: HashMap<i32> = pairs.into_iter().collect();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("foo"), Some(&3));
+        assert_eq!(map.get("bar"), Some(&2));
+    }
+
+    #[test]
+    fn test_append() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = HashMap::new();
+        let mut other = HashMap::new();
+        for i in 0..10 {
+            map.insert(&i.to_string(), (i, Counted(drops.clone())));
+        }
+        for i in 5..15 {
+            other.insert(&i.to_string(), (i * 10, Counted(drops.clone())));
+        }
+        other.insert_bytes(&[0xff], (-1, Counted(drops.clone())));
+
+        map.append(&mut other);
+        assert_eq!(drops.get(), 5);
+        assert_eq!(map.len(), 16);
+        for i in 0..15 {
+            let expected = if i < 5 { i } else { i * 10 };
+            assert_eq!(map.get(&i.to_string()).map(|v| v.0), Some(expected));
+        }
+        assert_eq!(map.get_bytes(&[0xff]).map(|v| v.0), Some(-1));
+
+        assert!(other.is_empty());
+        assert_eq!(other.iter_bytes().count(), 0);
+        other.insert("foo", (0, Counted(drops.clone())));
+        assert_eq!(other.len(), 1);
+        drop(map);
+        assert_eq!(drops.get(), 5 + 16);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut map = HashMap::new();
+        map.insert("foo", 1);
+        map.extend(vec![("foo".to_string(), 2), ("bar".to_string(), 3)]);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("foo"), Some(&2));
+        assert_eq!(map.get("bar"), Some(&3));
+    }
+
+    #[test]
+    fn test_extend_reserves_once() {
+        let pairs: Vec<_> = (0..10_000).map(|i| (i.to_string(), i)).collect();
+        let expected_buckets = HashMap::<i32>::with_capacity(pairs.len()).bucket_count();
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut map = HashMap::new();
+        map.on_resize(move |old, new| tx.send((old, new)).unwrap());
+        map.extend(pairs.clone());
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [(0, expected_buckets)]);
+        assert_eq!(map.bucket_count(), expected_buckets);
+
+        let map: HashMap<i32> = pairs.into_iter().collect();
+        assert_eq!(map.len(), 10_000);
+        assert_eq!(map.bucket_count(), expected_buckets);
     }
 
     #[test]
     fn test_many_insertions() {
         let mut map = HashMap::new();
         for i in 0..1000 {
-            assert_eq!(map.insert(&i.to_string(), Box::new(i)), None);
+            assert_eq!(map.insert(&i.to_string(), i), None);
         }
         for i in 0..1000 {
             assert_eq!(map.get(&i.to_string()), Some(&i));
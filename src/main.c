@@ -4,7 +4,7 @@
 #include <string.h>
 
 int main(void) {
-  HashMap *map = hashmap_new();
+  HashMap *map = hashmap_new(NULL);
   char *value1 = strdup("Hello, World!");
   char *value2 = strdup("hello world");
   char *value3 = strdup("value");
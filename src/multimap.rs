@@ -0,0 +1,156 @@
+//! Hashmap associating any number of values with each key.
+
+use alloc::{vec, vec::Vec};
+use core::fmt::{self, Debug};
+
+use crate::{HashMap, HashMapKey};
+
+/// Multimap built on a [`HashMap`], where inserting a key that is already
+/// present adds another value for it instead of replacing the old one. The
+/// values of a key are kept in the order they were inserted in, and a key is
+/// only present for as long as it has at least one value.
+///
+/// ```
+/// use bindgen_hashmap::MultiMap;
+///
+/// let mut map = MultiMap::new();
+/// map.insert("fruit", "apple");
+/// map.insert("fruit", "pear");
+/// assert_eq!(map.get_all("fruit").collect::<Vec<_>>(), [&"apple", &"pear"]);
+/// assert_eq!(map.remove_all("fruit"), ["apple", "pear"]);
+/// assert!(map.is_empty());
+/// ```
+pub struct MultiMap<V> {
+    /// Hashmap from each key to its values, none of which are empty
+    map: HashMap<Vec<V>>,
+    /// Number of values across all keys
+    len: usize,
+}
+
+impl<V> MultiMap<V> {
+    /// Create a new empty multimap.
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of values stored in the multimap, counting every
+    /// value of a key separately.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the multimap contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of distinct keys stored in the multimap.
+    pub fn key_count(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if the multimap contains at least one value for the given
+    /// key.
+    pub fn contains_key(&self, key: &(impl HashMapKey + ?Sized)) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Add a value for the given key after any values it already has. If a
+    /// string key contains an interior nul byte, nothing is inserted and the
+    /// value is dropped, as with [`HashMap::insert`].
+    ///
+    /// # Panics
+    /// Panics if the entry for a new key cannot be allocated.
+    pub fn insert(&mut self, key: &(impl HashMapKey + ?Sized), value: V) {
+        match self.map.get_mut(key) {
+            Some(values) => values.push(value),
+            None => {
+                if key.key_bytes().is_none() {
+                    return;
+                }
+                self.map.insert(key, vec![value]);
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Returns an iterator over the values of the given key in the order they
+    /// were inserted in, which is empty if the key is missing.
+    pub fn get_all(&self, key: &(impl HashMapKey + ?Sized)) -> impl Iterator<Item = &V> + '_ {
+        self.map.get(key).into_iter().flatten()
+    }
+
+    /// Remove the given key along with all of its values, returning the values
+    /// in the order they were inserted in. Returns an empty vector if the key
+    /// is missing.
+    pub fn remove_all(&mut self, key: &(impl HashMapKey + ?Sized)) -> Vec<V> {
+        let values = self.map.remove(key).map_or_else(Vec::new, |values| *values);
+        self.len -= values.len();
+        values
+    }
+
+    /// Remove every key and value from the multimap.
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.len = 0;
+    }
+}
+
+impl<V> Default for MultiMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Debug> Debug for MultiMap<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.map.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{tests::Counted, MultiMap};
+
+    #[test]
+    fn test_multimap() {
+        let drops = Rc::new(Cell::new(0));
+        let mut map = MultiMap::new();
+        for i in 0..10 {
+            map.insert(&(i % 3).to_string(), (i, Counted(drops.clone())));
+        }
+        map.insert("a\0b", (-1, Counted(drops.clone())));
+        assert_eq!(drops.get(), 1);
+        assert_eq!(map.len(), 10);
+        assert_eq!(map.key_count(), 3);
+
+        let values = |map: &MultiMap<(i32, Counted)>, key| {
+            map.get_all(key).map(|(i, _)| *i).collect::<Vec<_>>()
+        };
+        assert_eq!(values(&map, "0"), [0, 3, 6, 9]);
+        assert_eq!(values(&map, "1"), [1, 4, 7]);
+        assert_eq!(values(&map, "3"), []);
+
+        let removed = map.remove_all("0");
+        assert_eq!(
+            removed.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            [0, 3, 6, 9]
+        );
+        drop(removed);
+        assert_eq!(drops.get(), 5);
+        assert!(map.remove_all("0").is_empty());
+        assert!(!map.contains_key("0"));
+        assert_eq!(map.len(), 6);
+
+        map.insert("0", (10, Counted(drops.clone())));
+        assert_eq!(values(&map, "0"), [10]);
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(drops.get(), 12);
+    }
+}
@@ -0,0 +1,65 @@
+//! Allocation functions the C hashmap calls in place of `malloc`, `calloc`, and
+//! `free` when the `std` feature is disabled. They forward to the global Rust
+//! allocator, so the C library needs no hosted libc allocator.
+
+use alloc::alloc::{alloc, alloc_zeroed, dealloc, Layout};
+use core::{ffi::c_void, ptr};
+
+/// Size of the header stored in front of every allocation. Unlike the Rust
+/// allocator, `free` is not told the size of the allocation, so the header
+/// records it. Its size is also the alignment of every allocation, which
+/// matches the guarantee of `malloc` on common platforms.
+const HEADER: usize = 16;
+
+/// Layout of an allocation of the given size including its header, or `None`
+/// if the size overflows.
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER)?, HEADER).ok()
+}
+
+/// Allocate the given number of bytes with the given allocation function,
+/// writing the header and returning a pointer past it, or null on failure.
+///
+/// # Safety
+/// The allocation function must be one of the global allocator functions.
+unsafe fn allocate(size: usize, allocate: unsafe fn(Layout) -> *mut u8) -> *mut c_void {
+    let Some(layout) = layout(size) else {
+        return ptr::null_mut();
+    };
+    let base = allocate(layout);
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+    base.cast::<usize>().write(size);
+    base.add(HEADER).cast()
+}
+
+/// Allocate the given number of bytes like `malloc`.
+#[no_mangle]
+unsafe extern "C" fn hashmap_rust_malloc(size: usize) -> *mut c_void {
+    allocate(size, alloc)
+}
+
+/// Allocate zeroed memory for `count` elements of the given size like `calloc`.
+#[no_mangle]
+unsafe extern "C" fn hashmap_rust_calloc(count: usize, size: usize) -> *mut c_void {
+    match count.checked_mul(size) {
+        Some(size) => allocate(size, alloc_zeroed),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Free memory allocated by [`hashmap_rust_malloc`] or [`hashmap_rust_calloc`]
+/// like `free`. Null pointers are ignored.
+#[no_mangle]
+unsafe extern "C" fn hashmap_rust_free(ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    let base = ptr.cast::<u8>().sub(HEADER);
+    let size = base.cast::<usize>().read();
+    dealloc(
+        base,
+        layout(size).expect("allocated size has a valid layout"),
+    );
+}
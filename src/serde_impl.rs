@@ -0,0 +1,85 @@
+//! [`serde`] support for [`HashMap`], enabled by the `serde` feature. Hashmaps
+//! are serialized as maps with string keys.
+
+use alloc::string::String;
+use core::{fmt, marker::PhantomData, str};
+
+use serde::{
+    de::{self, MapAccess, Visitor},
+    ser::{self, SerializeMap},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::HashMap;
+
+impl<V: Serialize> Serialize for HashMap<V> {
+    /// Serialize the hashmap as a map. Fails if a key inserted through
+    /// [`HashMap::insert_bytes`] is not valid UTF-8.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        let mut iter = self.iter();
+        while let Some((key, value)) = iter.next_bytes() {
+            let key = str::from_utf8(key)
+                .map_err(|_| ser::Error::custom("hashmap key is not valid UTF-8"))?;
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Visitor that builds a [`HashMap`] out of a serialized map.
+struct HashMapVisitor<V> {
+    /// Phantom data for the type of values being deserialized
+    value: PhantomData<V>,
+}
+
+impl<'de, V: Deserialize<'de>> Visitor<'de> for HashMapVisitor<V> {
+    type Value = HashMap<V>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a map with string keys")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        // Don't trust the size hint of untrusted input too far
+        let mut map = HashMap::with_capacity(access.size_hint().unwrap_or(0).min(4096));
+        while let Some((key, value)) = access.next_entry::<String, V>()? {
+            map.try_insert(&key, value).map_err(de::Error::custom)?;
+        }
+        Ok(map)
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for HashMap<V> {
+    /// Deserialize a hashmap from a map. Later values win over earlier ones with
+    /// the same key and keys containing interior nul bytes are rejected.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(HashMapVisitor { value: PhantomData })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::HashMap;
+
+    #[test]
+    fn test_round_trip() {
+        let map: HashMap<i32> = (0..100).map(|i| (i.to_string(), i)).collect();
+        let json = serde_json::to_string(&map).unwrap();
+        let round_tripped: HashMap<i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(map, round_tripped);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut map = HashMap::new();
+        assert_eq!(serde_json::to_string(&map).unwrap(), "{}");
+        map.insert("foo", vec![1, 2]);
+        assert_eq!(serde_json::to_string(&map).unwrap(), r#"{"foo":[1,2]}"#);
+    }
+
+    #[test]
+    fn test_deserialize_invalid_key() {
+        assert!(serde_json::from_str::<HashMap<i32>>(r#"{"foo\u0000bar":1}"#).is_err());
+    }
+}
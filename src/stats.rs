@@ -0,0 +1,31 @@
+//! Statistics about the internal layout of a [`HashMap`](crate::HashMap).
+
+use crate::hashmap;
+
+/// Snapshot of how the keys of a hashmap are distributed across its buckets,
+/// created by [`HashMap::bucket_stats`](crate::HashMap::bucket_stats). Long
+/// chains or many empty buckets for the number of keys point to a hash function
+/// that is a poor fit for the keys.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BucketStats {
+    /// Number of buckets in the hashmap
+    pub bucket_count: usize,
+    /// Length of the longest chain of keys sharing a bucket
+    pub max_chain_len: usize,
+    /// Mean length of the chains in the buckets that are not empty, or 0 if
+    /// every bucket is empty
+    pub mean_chain_len: f64,
+    /// Number of buckets without any keys
+    pub empty_buckets: usize,
+}
+
+impl From<hashmap::HashMapStats> for BucketStats {
+    fn from(stats: hashmap::HashMapStats) -> Self {
+        Self {
+            bucket_count: stats.buckets,
+            max_chain_len: stats.max_chain_len,
+            mean_chain_len: stats.mean_chain_len,
+            empty_buckets: stats.empty_buckets,
+        }
+    }
+}